@@ -0,0 +1,243 @@
+use std::fmt;
+
+/// Where a node sits in the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column (in characters) of the first char
+    pub column: usize,
+    /// byte range within the line
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Script {
+    pub statements: Vec<Spanned<Statement>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    /// `img <file> [as <alias>] [repeat <n>] <command>*`
+    Image(ImageStatement),
+    /// `let <name> = <expr>`, shared by every `$<name>`
+    Let(LetStatement),
+    /// `seed <n>`, makes everything random reproducible
+    Seed(u64),
+    /// `bpm <tempo>`
+    Bpm(f32),
+    /// `reload`, decode all images from disk again
+    Reload,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageStatement {
+    pub file: Spanned<String>,
+    /// `as tree1`, so the same file can be used by several layers
+    pub alias: Option<Spanned<String>>,
+    /// `repeat 20`, creates that many layers from the same line
+    pub repeat: Option<Spanned<usize>>,
+    pub commands: Vec<Spanned<Command>>,
+}
+
+impl ImageStatement {
+    /// The name of the layer this creates, the alias if there is one.
+    pub fn layer_name(&self) -> &str {
+        match &self.alias {
+            Some(alias) => &alias.node,
+            None => &self.file.node,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LetStatement {
+    pub name: Spanned<String>,
+    pub value: Spanned<ParamExpr>,
+}
+
+/// A command like `pos 0 [bounce 0 100 200]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub name: Spanned<String>,
+    pub args: Vec<Spanned<ParamExpr>>,
+}
+
+/// Anything that can be turned into a `Parameter`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamExpr {
+    Scalar(f32),
+    /// `4s`, `500ms` or `2b`, only as the length of a generator
+    Duration(f32, TimeUnit),
+    Instance(InstanceValue),
+    /// a bare word among generator arguments, like `in-out-cubic`
+    Keyword(String),
+    Generator(Generator),
+    /// `$wob`, a value defined with `let`
+    Var(String),
+    /// `-[bounce 0 1 100]`
+    Neg(Box<Spanned<ParamExpr>>),
+    /// `[bounce -200 200 300] + [choose -20 0 20]`
+    Binary(BinOp, Box<Spanned<ParamExpr>>, Box<Spanned<ParamExpr>>),
+}
+
+impl ParamExpr {
+    /// Whether the value stays the same from frame to frame.
+    pub fn is_constant(&self) -> bool {
+        match self {
+            ParamExpr::Scalar(_)
+            | ParamExpr::Duration(..)
+            | ParamExpr::Instance(_)
+            | ParamExpr::Keyword(_) => true,
+            ParamExpr::Generator(_) | ParamExpr::Var(_) => false,
+            ParamExpr::Neg(inner) => inner.node.is_constant(),
+            ParamExpr::Binary(_, left, right) => {
+                left.node.is_constant() && right.node.is_constant()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinOp {
+    /// Dividing by zero gives zero, an infinite size or position isn't
+    /// of much use.
+    pub fn apply(self, left: f32, right: f32) -> f32 {
+        match self {
+            BinOp::Add => left + right,
+            BinOp::Sub => left - right,
+            BinOp::Mul => left * right,
+            BinOp::Div if right == 0.0 => 0.0,
+            BinOp::Div => left / right,
+        }
+    }
+
+    pub fn symbol(self) -> char {
+        match self {
+            BinOp::Add => '+',
+            BinOp::Sub => '-',
+            BinOp::Mul => '*',
+            BinOp::Div => '/',
+        }
+    }
+
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Add | BinOp::Sub => 1,
+            BinOp::Mul | BinOp::Div => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeUnit {
+    Seconds,
+    Beats,
+}
+
+/// Numbers that differ between the layers of a repeated `img` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstanceValue {
+    /// `index`, counting from 0
+    Index,
+    /// `n`, the number of instances
+    Count,
+}
+
+/// A bracketed generator like `[ramp 0 1 100]`. Arguments are constant
+/// expressions, i.e. `[ramp 0 (index * 10) 100]`, or keywords.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generator {
+    pub name: Spanned<String>,
+    pub args: Vec<Spanned<ParamExpr>>,
+    /// `/4` at the end, step on every quarter beat instead of every frame
+    pub sync: Option<Spanned<f32>>,
+    /// `| lag 100ms | quant 50`, applied in order after the sync
+    pub modifiers: Vec<Modifier>,
+}
+
+/// Post-processes the value of a generator, like `quant 50`. Arguments are
+/// constant expressions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modifier {
+    pub name: Spanned<String>,
+    pub args: Vec<Spanned<ParamExpr>>,
+}
+
+/// Formats to a normalized form of the source, i.e. `[ramp 0 1 100]`.
+/// Two expressions that format the same behave the same.
+impl fmt::Display for ParamExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamExpr::Scalar(val) => write!(f, "{}", val),
+            ParamExpr::Duration(val, TimeUnit::Seconds) => write!(f, "{}s", val),
+            ParamExpr::Duration(val, TimeUnit::Beats) => write!(f, "{}b", val),
+            ParamExpr::Instance(InstanceValue::Index) => write!(f, "index"),
+            ParamExpr::Instance(InstanceValue::Count) => write!(f, "n"),
+            ParamExpr::Keyword(word) => write!(f, "{}", word),
+            ParamExpr::Generator(gen) => write!(f, "{}", gen),
+            ParamExpr::Var(name) => write!(f, "${}", name),
+            ParamExpr::Neg(inner) => match inner.node {
+                ParamExpr::Binary(..) => write!(f, "-({})", inner.node),
+                _ => write!(f, "-{}", inner.node),
+            },
+            ParamExpr::Binary(op, left, right) => {
+                // only as many parentheses as needed to keep the meaning
+                match left.node {
+                    ParamExpr::Binary(left_op, ..) if left_op.precedence() < op.precedence() => {
+                        write!(f, "({})", left.node)?
+                    }
+                    _ => write!(f, "{}", left.node)?,
+                }
+                write!(f, " {} ", op.symbol())?;
+                match right.node {
+                    ParamExpr::Binary(right_op, ..) if right_op.precedence() <= op.precedence() => {
+                        write!(f, "({})", right.node)
+                    }
+                    _ => write!(f, "{}", right.node),
+                }
+            }
+        }
+    }
+}
+
+/// `name arg arg`, with binary arguments in parentheses
+fn write_call(f: &mut fmt::Formatter<'_>, name: &str, args: &[Spanned<ParamExpr>]) -> fmt::Result {
+    write!(f, "{}", name)?;
+    for arg in args.iter() {
+        match arg.node {
+            ParamExpr::Binary(..) => write!(f, " ({})", arg.node)?,
+            _ => write!(f, " {}", arg.node)?,
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        write_call(f, &self.name.node, &self.args)?;
+        if let Some(sync) = &self.sync {
+            write!(f, " /{}", sync.node)?;
+        }
+        for modifier in self.modifiers.iter() {
+            write!(f, " | ")?;
+            write_call(f, &modifier.name.node, &modifier.args)?;
+        }
+        write!(f, "]")
+    }
+}
@@ -0,0 +1,119 @@
+/// Tempo until something says otherwise.
+pub const DEFAULT_BPM: f64 = 120.0;
+pub const BEATS_PER_BAR: f64 = 4.0;
+
+/// Adding up frame lengths isn't exact, this makes sure that a beat that
+/// should be whole counts as one.
+const EPSILON: f64 = 1e-9;
+
+/// Time as parameters see it. Advanced once per frame by the scene, by the
+/// length of a frame, so it doesn't matter how fast frames are rendered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clock {
+    frame: u64,
+    seconds: f64,
+    beats: f64,
+    bpm: f64,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Clock {
+            frame: 0,
+            seconds: 0.0,
+            beats: 0.0,
+            bpm: DEFAULT_BPM,
+        }
+    }
+
+    /// Move on to the next frame, `dt` seconds later.
+    pub fn advance(&mut self, dt: f64) {
+        self.frame += 1;
+        self.seconds += dt;
+        self.beats += dt * self.bpm / 60.0;
+    }
+
+    /// Frames since the start.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Seconds since the start.
+    pub fn seconds(&self) -> f64 {
+        self.seconds
+    }
+
+    /// Beats since the start, at whatever tempo it was at the time.
+    pub fn beats(&self) -> f64 {
+        self.beats
+    }
+
+    pub fn bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    /// Change the tempo from now on, without jumping within the beat.
+    pub fn set_bpm(&mut self, bpm: f64) {
+        self.bpm = bpm;
+    }
+
+    /// How far into the current beat, from 0 to 1.
+    pub fn beat_phase(&self) -> f64 {
+        (self.beats + EPSILON).fract()
+    }
+
+    /// Bars since the start, counting from 0.
+    pub fn bar(&self) -> u64 {
+        ((self.beats + EPSILON) / BEATS_PER_BAR) as u64
+    }
+
+    /// How far into the current bar, from 0 to 1.
+    pub fn bar_phase(&self) -> f64 {
+        ((self.beats + EPSILON) / BEATS_PER_BAR).fract()
+    }
+
+    /// Cycles of `length` beats since the start, so whole numbers fall on
+    /// the beat grid.
+    pub fn cycles(&self, length: f64) -> f64 {
+        (self.beats + EPSILON) / length
+    }
+
+    /// Counts `division` ticks per beat, `1` is once per beat, `4` every
+    /// sixteenth note.
+    pub fn tick(&self, division: f64) -> u64 {
+        ((self.beats + EPSILON) * division) as u64
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock() {
+        let mut clock = Clock::new();
+        for _ in 0..24 {
+            clock.advance(1.0 / 24.0);
+        }
+        assert_eq!(clock.frame(), 24);
+        assert!((clock.seconds() - 1.0).abs() < 1e-9);
+        assert!((clock.beats() - 2.0).abs() < 1e-9);
+
+        clock.set_bpm(60.0);
+        for _ in 0..36 {
+            clock.advance(1.0 / 24.0);
+        }
+        assert!((clock.beats() - 3.5).abs() < 1e-9);
+        assert!((clock.beat_phase() - 0.5).abs() < 1e-9);
+        assert_eq!(clock.bar(), 0);
+        assert!((clock.bar_phase() - 0.875).abs() < 1e-9);
+        assert_eq!(clock.tick(4.0), 14);
+        assert!((clock.cycles(2.0) - 1.75).abs() < 1e-9);
+    }
+}
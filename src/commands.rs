@@ -0,0 +1,194 @@
+use std::fmt;
+
+use crate::ast::BinOp;
+use crate::img_params::ImgParams;
+use crate::parameter::{
+    BinaryParameter, Edge, Parameter, SharedParameter, StaticParameter, WalkParameter,
+};
+
+/// How many steps `brownian` strays from the position at most.
+const BROWNIAN_STEPS: f32 = 25.0;
+
+/// What an argument to a command means.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgType {
+    /// canvas pixels
+    Pixels,
+    /// fraction of the source image, 0 to 1
+    Fraction,
+    /// degrees
+    Degrees,
+    /// a plain amount, like a blur sigma or a contrast factor
+    Amount,
+}
+
+impl fmt::Display for ArgType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ArgType::Pixels => "pixels",
+            ArgType::Fraction => "fraction",
+            ArgType::Degrees => "degrees",
+            ArgType::Amount => "amount",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub struct Arg {
+    pub name: &'static str,
+    pub ty: ArgType,
+}
+
+const fn arg(name: &'static str, ty: ArgType) -> Arg {
+    Arg { name, ty }
+}
+
+/// A command that can follow an `img` statement. Every argument is a
+/// parameter, `build` receives exactly `args.len()` of them.
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub args: &'static [Arg],
+    pub build: fn(Vec<Box<dyn Parameter>>) -> ImgParams,
+}
+
+impl CommandSpec {
+    pub fn arity(&self) -> usize {
+        self.args.len()
+    }
+
+    /// i.e. `pos <x:pixels> <y:pixels>`
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_string();
+        for arg in self.args.iter() {
+            usage.push_str(&format!(" <{}:{}>", arg.name, arg.ty));
+        }
+        usage
+    }
+}
+
+/// Turn the argument vector into an array, `build` is only ever called with
+/// the declared arity.
+fn unpack<const N: usize>(args: Vec<Box<dyn Parameter>>) -> [Box<dyn Parameter>; N] {
+    match args.try_into() {
+        Ok(args) => args,
+        Err(_) => panic!("wrong number of arguments"),
+    }
+}
+
+pub static COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "pos",
+        args: &[arg("x", ArgType::Pixels), arg("y", ArgType::Pixels)],
+        build: |args| {
+            let [x, y] = unpack(args);
+            ImgParams::Position(x, y)
+        },
+    },
+    CommandSpec {
+        name: "size",
+        args: &[arg("w", ArgType::Pixels), arg("h", ArgType::Pixels)],
+        build: |args| {
+            let [w, h] = unpack(args);
+            ImgParams::Size(w, h)
+        },
+    },
+    CommandSpec {
+        name: "crop",
+        args: &[
+            arg("x", ArgType::Fraction),
+            arg("y", ArgType::Fraction),
+            arg("w", ArgType::Fraction),
+            arg("h", ArgType::Fraction),
+        ],
+        build: |args| {
+            let [x, y, w, h] = unpack(args);
+            ImgParams::Crop(x, y, w, h)
+        },
+    },
+    CommandSpec {
+        name: "blur",
+        args: &[arg("sigma", ArgType::Amount)],
+        build: |args| {
+            let [f] = unpack(args);
+            ImgParams::Blur(f)
+        },
+    },
+    CommandSpec {
+        name: "brighten",
+        args: &[arg("value", ArgType::Amount)],
+        build: |args| {
+            let [f] = unpack(args);
+            ImgParams::Brighten(f)
+        },
+    },
+    CommandSpec {
+        name: "huerot",
+        args: &[arg("angle", ArgType::Degrees)],
+        build: |args| {
+            let [f] = unpack(args);
+            ImgParams::HueRot(f)
+        },
+    },
+    CommandSpec {
+        name: "contrast",
+        args: &[arg("contrast", ArgType::Amount)],
+        build: |args| {
+            let [f] = unpack(args);
+            ImgParams::Contrast(f)
+        },
+    },
+    CommandSpec {
+        name: "opacity",
+        args: &[arg("opacity", ArgType::Fraction)],
+        build: |args| {
+            let [f] = unpack(args);
+            ImgParams::Opacity(f)
+        },
+    },
+    CommandSpec {
+        name: "scatter",
+        args: &[arg("amount", ArgType::Amount)],
+        build: |args| {
+            let [f] = unpack(args);
+            ImgParams::Scatter(f)
+        },
+    },
+    CommandSpec {
+        name: "brownian",
+        args: &[arg("step", ArgType::Pixels)],
+        // `<step> * [walk 0 -25 25 1]` on both axes, so both take steps of
+        // the same size and stay within 25 steps of the position
+        build: |args| {
+            let [f] = unpack(args);
+            let step = SharedParameter::new(f);
+            let axis = |step: SharedParameter| -> Box<dyn Parameter> {
+                let walk = WalkParameter::new(
+                    0.0,
+                    -BROWNIAN_STEPS,
+                    BROWNIAN_STEPS,
+                    Box::new(StaticParameter::from_val(1.0)),
+                    Edge::Reflect,
+                );
+                Box::new(BinaryParameter::new(
+                    BinOp::Mul,
+                    Box::new(step),
+                    Box::new(walk),
+                ))
+            };
+            ImgParams::Brownian(axis(step.clone()), axis(step))
+        },
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.name == name)
+}
+
+/// All command names, for error messages.
+pub fn names() -> String {
+    COMMANDS
+        .iter()
+        .map(|spec| spec.name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,243 @@
+use image::{imageops, imageops::FilterType, Rgba, RgbaImage};
+
+use crate::layer::Stamp;
+
+/// After this many stamps the canvas starts over, same as the
+/// texture list in the nannou frontend.
+pub const MAX_STAMPS: usize = 500;
+
+/// Software version of the nannou view. Stamps are scaled to their size and
+/// drawn centered at their position, with the origin at the canvas center
+/// and y pointing up, on top of everything drawn since the last clear.
+pub struct Canvas {
+    image: RgbaImage,
+    background: Rgba<u8>,
+    stamps: usize,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Self {
+        let background = Rgba([0, 0, 0, 255]);
+        Canvas {
+            image: RgbaImage::from_pixel(width, height, background),
+            background,
+            stamps: 0,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.image.height()
+    }
+
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    pub fn clear(&mut self) {
+        for p in self.image.pixels_mut() {
+            *p = self.background;
+        }
+        self.stamps = 0;
+    }
+
+    /// Resizing starts over with an empty canvas.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.image = RgbaImage::from_pixel(width, height, self.background);
+        self.stamps = 0;
+    }
+
+    /// Map view coordinates (origin at center, y up) to pixel coordinates
+    /// (origin top left, y down).
+    pub fn to_pixel(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            self.width() as f32 / 2.0 + x,
+            self.height() as f32 / 2.0 - y,
+        )
+    }
+
+    pub fn draw(&mut self, stamp: &Stamp) {
+        if self.stamps >= MAX_STAMPS {
+            self.clear();
+        }
+        self.stamps += 1;
+
+        let w = stamp.w.abs().round().max(1.0);
+        let h = stamp.h.abs().round().max(1.0);
+        let (cx, cy) = self.to_pixel(stamp.x, stamp.y);
+        let left = (cx - w / 2.0).round() as i64;
+        let top = (cy - h / 2.0).round() as i64;
+
+        // the part of the canvas the stamp covers
+        let x0 = left.max(0);
+        let y0 = top.max(0);
+        let x1 = left.saturating_add(w as i64).min(self.width() as i64);
+        let y1 = top.saturating_add(h as i64).min(self.height() as i64);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        // shrinking happens up front, that never takes more memory than the
+        // image itself. Growing is done per visible pixel, so a huge stamp
+        // doesn't cost more than the canvas.
+        let mut source = stamp.image.to_rgba8();
+        let (sw, sh) = source.dimensions();
+        if (w as u32) < sw || (h as u32) < sh {
+            source = imageops::resize(
+                &source,
+                (w as u32).min(sw),
+                (h as u32).min(sh),
+                FilterType::Triangle,
+            );
+        }
+        // negative sizes mirror the image, like they do on the GPU
+        if stamp.w < 0.0 {
+            imageops::flip_horizontal_in_place(&mut source);
+        }
+        if stamp.h < 0.0 {
+            imageops::flip_vertical_in_place(&mut source);
+        }
+
+        let scale_x = source.width() as f32 / w;
+        let scale_y = source.height() as f32 / h;
+        for y in y0..y1 {
+            let v = ((y - top) as f32 + 0.5) * scale_y - 0.5;
+            for x in x0..x1 {
+                let u = ((x - left) as f32 + 0.5) * scale_x - 0.5;
+                let pixel = sample_bilinear(&source, u, v);
+                blend_over(self.image.get_pixel_mut(x as u32, y as u32), &pixel);
+            }
+        }
+    }
+}
+
+/// The color at `u`/`v` in pixel coordinates, blending the four nearest
+/// pixels. Outside the image, the nearest edge pixel counts.
+fn sample_bilinear(image: &RgbaImage, u: f32, v: f32) -> Rgba<u8> {
+    let max_x = image.width() as f32 - 1.0;
+    let max_y = image.height() as f32 - 1.0;
+    let u = u.clamp(0.0, max_x);
+    let v = v.clamp(0.0, max_y);
+    let (x0, y0) = (u.floor(), v.floor());
+    let (x1, y1) = ((x0 + 1.0).min(max_x), (y0 + 1.0).min(max_y));
+    let (fx, fy) = (u - x0, v - y0);
+
+    let p00 = image.get_pixel(x0 as u32, y0 as u32);
+    let p10 = image.get_pixel(x1 as u32, y0 as u32);
+    let p01 = image.get_pixel(x0 as u32, y1 as u32);
+    let p11 = image.get_pixel(x1 as u32, y1 as u32);
+    let mut out = Rgba([0, 0, 0, 0]);
+    for c in 0..4 {
+        let top = p00[c] as f32 * (1.0 - fx) + p10[c] as f32 * fx;
+        let bottom = p01[c] as f32 * (1.0 - fx) + p11[c] as f32 * fx;
+        out[c] = (top * (1.0 - fy) + bottom * fy).round() as u8;
+    }
+    out
+}
+
+/// Porter-Duff "over", so an opaque canvas stays opaque.
+fn blend_over(dst: &mut Rgba<u8>, src: &Rgba<u8>) {
+    let sa = src[3] as f32 / 255.0;
+    let da = dst[3] as f32 / 255.0;
+    let out_a = sa + da * (1.0 - sa);
+    if out_a <= 0.0 {
+        *dst = Rgba([0, 0, 0, 0]);
+        return;
+    }
+    for c in 0..3 {
+        let v = (src[c] as f32 * sa + dst[c] as f32 * da * (1.0 - sa)) / out_a;
+        dst[c] = v.round() as u8;
+    }
+    dst[3] = (out_a * 255.0).round() as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::DynamicImage;
+
+    fn stamp(color: [u8; 4], x: f32, y: f32, w: f32, h: f32) -> Stamp {
+        Stamp {
+            image: DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba(color))),
+            x,
+            y,
+            w,
+            h,
+        }
+    }
+
+    #[test]
+    fn test_canvas_coordinates() {
+        let mut canvas = Canvas::new(100, 80);
+        canvas.draw(&stamp([255, 0, 0, 255], 0.0, 0.0, 10.0, 10.0));
+        canvas.draw(&stamp([0, 255, 0, 255], -40.0, 30.0, 10.0, 10.0));
+
+        assert_eq!(*canvas.image().get_pixel(50, 40), Rgba([255, 0, 0, 255]));
+        assert_eq!(*canvas.image().get_pixel(45, 35), Rgba([255, 0, 0, 255]));
+        assert_eq!(*canvas.image().get_pixel(44, 34), Rgba([0, 0, 0, 255]));
+        // top left is negative x, positive y
+        assert_eq!(*canvas.image().get_pixel(10, 10), Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_canvas_clipping_and_blending() {
+        let mut canvas = Canvas::new(20, 20);
+        // half off the right edge
+        canvas.draw(&stamp([255, 255, 255, 255], 10.0, 0.0, 10.0, 10.0));
+        assert_eq!(
+            *canvas.image().get_pixel(19, 10),
+            Rgba([255, 255, 255, 255])
+        );
+        // fully outside
+        canvas.draw(&stamp([255, 255, 255, 255], 500.0, 0.0, 10.0, 10.0));
+
+        // half transparent over black
+        canvas.draw(&stamp([255, 255, 255, 128], -5.0, 0.0, 4.0, 4.0));
+        let p = canvas.image().get_pixel(5, 10);
+        assert!(p[0] > 100 && p[0] < 160);
+        assert_eq!(p[3], 255);
+    }
+
+    #[test]
+    fn test_canvas_huge_stamp() {
+        let mut canvas = Canvas::new(64, 64);
+        canvas.draw(&stamp([255, 0, 0, 255], 0.0, 0.0, 200000.0, 200000.0));
+        assert!(canvas
+            .image()
+            .pixels()
+            .all(|p| *p == Rgba([255, 0, 0, 255])));
+
+        // left half red, right half green, mirrored by a negative width
+        let mut image = RgbaImage::from_pixel(2, 1, Rgba([255, 0, 0, 255]));
+        image.put_pixel(1, 0, Rgba([0, 255, 0, 255]));
+        let huge = |x| Stamp {
+            image: DynamicImage::ImageRgba8(image.clone()),
+            x,
+            y: 0.0,
+            w: -100000.0,
+            h: 100000.0,
+        };
+        let mut canvas = Canvas::new(20, 20);
+        // the canvas looks at the middle of the left half
+        canvas.draw(&huge(25000.0));
+        assert_eq!(*canvas.image().get_pixel(10, 10), Rgba([0, 255, 0, 255]));
+        canvas.draw(&huge(-25000.0));
+        assert_eq!(*canvas.image().get_pixel(10, 10), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_canvas_starts_over() {
+        let mut canvas = Canvas::new(20, 20);
+        canvas.draw(&stamp([255, 0, 0, 255], -5.0, 0.0, 2.0, 2.0));
+        for _ in 1..MAX_STAMPS {
+            canvas.draw(&stamp([0, 255, 0, 255], 5.0, 0.0, 2.0, 2.0));
+        }
+        assert_eq!(*canvas.image().get_pixel(5, 10), Rgba([255, 0, 0, 255]));
+
+        canvas.draw(&stamp([0, 255, 0, 255], 5.0, 0.0, 2.0, 2.0));
+        assert_eq!(*canvas.image().get_pixel(5, 10), Rgba([0, 0, 0, 255]));
+    }
+}
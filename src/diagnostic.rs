@@ -0,0 +1,76 @@
+use std::fmt;
+
+use crate::ast::Span;
+
+/// Something that went wrong while parsing or interpreting the code,
+/// pinned to the place in the text where it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column (in characters)
+    pub column: usize,
+    pub message: String,
+    pub expected: Option<String>,
+    pub found: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Diagnostic {
+            line,
+            column,
+            message: message.into(),
+            expected: None,
+            found: None,
+        }
+    }
+
+    pub fn at(span: Span, message: impl Into<String>) -> Self {
+        Diagnostic::new(span.line, span.column, message)
+    }
+
+    pub fn expected(mut self, expected: impl Into<String>) -> Self {
+        self.expected = Some(expected.into());
+        self
+    }
+
+    pub fn found(mut self, found: impl Into<String>) -> Self {
+        self.found = Some(found.into());
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)?;
+        if let Some(expected) = &self.expected {
+            write!(f, ", expected {}", expected)?;
+        }
+        if let Some(found) = &self.found {
+            write!(f, ", found `{}`", found)?;
+        }
+        Ok(())
+    }
+}
+
+/// Convert a byte offset within `line` into a 1-based character column.
+pub fn column_of(line: &str, offset: usize) -> usize {
+    line[..offset.min(line.len())].chars().count() + 1
+}
+
+/// The word starting at `rest`, used to show what the parser tripped over.
+pub fn word_at(rest: &str) -> String {
+    let word: String = rest
+        .chars()
+        .take_while(|c| !c.is_whitespace() && *c != '[' && *c != ']')
+        .collect();
+    if word.is_empty() {
+        match rest.chars().next() {
+            Some(c) => c.to_string(),
+            None => "end of line".to_string(),
+        }
+    } else {
+        word
+    }
+}
@@ -0,0 +1,131 @@
+use std::f32::consts::TAU;
+
+/// The shape of an easing curve, as it starts (the "in" part).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    Quad,
+    Cubic,
+    Expo,
+    Elastic,
+    Back,
+    Bounce,
+}
+
+/// Maps 0 to 0 and 1 to 1, with something more interesting than a
+/// straight line in between. `Elastic` and `Back` overshoot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    In(Curve),
+    Out(Curve),
+    InOut(Curve),
+}
+
+pub const CURVES: &str = "quad, cubic, expo, elastic, back or bounce";
+
+impl Curve {
+    fn from_name(name: &str) -> Option<Curve> {
+        match name {
+            "quad" => Some(Curve::Quad),
+            "cubic" => Some(Curve::Cubic),
+            "expo" => Some(Curve::Expo),
+            "elastic" => Some(Curve::Elastic),
+            "back" => Some(Curve::Back),
+            "bounce" => Some(Curve::Bounce),
+            _ => None,
+        }
+    }
+
+    fn ease_in(self, t: f32) -> f32 {
+        match self {
+            Curve::Quad => t * t,
+            Curve::Cubic => t * t * t,
+            Curve::Expo if t <= 0.0 => 0.0,
+            Curve::Expo => 2f32.powf(10.0 * t - 10.0),
+            Curve::Elastic if t <= 0.0 || t >= 1.0 => t,
+            Curve::Elastic => {
+                -(2f32.powf(10.0 * t - 10.0)) * ((10.0 * t - 10.75) * TAU / 3.0).sin()
+            }
+            Curve::Back => {
+                let c1 = 1.70158;
+                (c1 + 1.0) * t * t * t - c1 * t * t
+            }
+            Curve::Bounce => 1.0 - bounce_out(1.0 - t),
+        }
+    }
+}
+
+/// The classic bouncing ball, landing at 1.
+fn bounce_out(t: f32) -> f32 {
+    let n1 = 7.5625;
+    let d1 = 2.75;
+    if t < 1.0 / d1 {
+        n1 * t * t
+    } else if t < 2.0 / d1 {
+        let t = t - 1.5 / d1;
+        n1 * t * t + 0.75
+    } else if t < 2.5 / d1 {
+        let t = t - 2.25 / d1;
+        n1 * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / d1;
+        n1 * t * t + 0.984375
+    }
+}
+
+impl Easing {
+    /// Parses names like `in-out-cubic`, `ease-in-out-cubic` or `linear`.
+    pub fn from_name(name: &str) -> Option<Easing> {
+        let name = name.strip_prefix("ease-").unwrap_or(name);
+        if name == "linear" {
+            return Some(Easing::Linear);
+        }
+        if let Some(curve) = name.strip_prefix("in-out-") {
+            Curve::from_name(curve).map(Easing::InOut)
+        } else if let Some(curve) = name.strip_prefix("in-") {
+            Curve::from_name(curve).map(Easing::In)
+        } else if let Some(curve) = name.strip_prefix("out-") {
+            Curve::from_name(curve).map(Easing::Out)
+        } else {
+            None
+        }
+    }
+
+    /// Reshape `t`, which should be between 0 and 1.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::In(curve) => curve.ease_in(t),
+            Easing::Out(curve) => 1.0 - curve.ease_in(1.0 - t),
+            Easing::InOut(curve) if t < 0.5 => curve.ease_in(2.0 * t) / 2.0,
+            Easing::InOut(curve) => 1.0 - curve.ease_in(2.0 - 2.0 * t) / 2.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_easing() {
+        for curve in ["quad", "cubic", "expo", "elastic", "back", "bounce"] {
+            for kind in ["in", "out", "in-out"] {
+                let easing = Easing::from_name(&format!("{}-{}", kind, curve)).unwrap();
+                assert!(easing.apply(0.0).abs() < 1e-3, "{:?}", easing);
+                assert!((easing.apply(1.0) - 1.0).abs() < 1e-3, "{:?}", easing);
+            }
+        }
+
+        let cubic = Easing::from_name("ease-in-out-cubic").unwrap();
+        assert_eq!(cubic, Easing::InOut(Curve::Cubic));
+        assert_eq!(cubic.apply(0.25), 0.0625);
+        assert_eq!(cubic.apply(0.75), 0.9375);
+        assert!(Easing::In(Curve::Back).apply(0.2) < 0.0);
+        assert!((Easing::Out(Curve::Quad).apply(0.5) - 0.75).abs() < 1e-6);
+        assert!((Easing::Out(Curve::Bounce).apply(1.0 / 2.75) - 1.0).abs() < 1e-6);
+        assert_eq!(Easing::from_name("linear"), Some(Easing::Linear));
+        assert_eq!(Easing::from_name("in-cubes"), None);
+    }
+}
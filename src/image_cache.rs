@@ -0,0 +1,170 @@
+use image::{DynamicImage, ImageResult};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// 512 MiB of decoded pixels.
+pub const DEFAULT_BUDGET: usize = 512 * 1024 * 1024;
+
+struct CacheEntry {
+    modified: Option<SystemTime>,
+    image: Arc<DynamicImage>,
+    bytes: usize,
+    last_used: u64,
+}
+
+/// Decoded images, keyed by path and modification time, so re-evaluating
+/// the code doesn't decode every file again. When the decoded images take
+/// up more than the budget, the least recently used ones are dropped.
+pub struct ImageCache {
+    entries: HashMap<PathBuf, CacheEntry>,
+    budget: usize,
+    used: usize,
+    tick: u64,
+}
+
+impl ImageCache {
+    pub fn new(budget: usize) -> Self {
+        ImageCache {
+            entries: HashMap::new(),
+            budget,
+            used: 0,
+            tick: 0,
+        }
+    }
+
+    /// The decoded image at `path`, from the cache unless the file changed
+    /// since it was loaded.
+    pub fn get(&mut self, path: &Path) -> ImageResult<Arc<DynamicImage>> {
+        self.tick += 1;
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+
+        if let Some(entry) = self.entries.get_mut(path) {
+            if entry.modified == modified && modified.is_some() {
+                entry.last_used = self.tick;
+                return Ok(entry.image.clone());
+            }
+        }
+
+        let image = Arc::new(image::open(path)?);
+        let bytes = image.as_bytes().len();
+
+        if let Some(old) = self.entries.insert(
+            path.to_path_buf(),
+            CacheEntry {
+                modified,
+                image: image.clone(),
+                bytes,
+                last_used: self.tick,
+            },
+        ) {
+            self.used -= old.bytes;
+        }
+        self.used += bytes;
+        self.evict(path);
+
+        Ok(image)
+    }
+
+    /// Forget everything, the next `get` decodes from disk.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.used = 0;
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict(Path::new(""));
+    }
+
+    /// Bytes of decoded pixels currently held.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drop least recently used entries until we're within budget,
+    /// never dropping `keep`.
+    fn evict(&mut self, keep: &Path) {
+        while self.used > self.budget {
+            let oldest = self
+                .entries
+                .iter()
+                .filter(|(path, _)| path.as_path() != keep)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone());
+
+            match oldest.and_then(|path| self.entries.remove(&path)) {
+                Some(entry) => self.used -= entry.bytes,
+                None => break,
+            }
+        }
+    }
+}
+
+impl Default for ImageCache {
+    fn default() -> Self {
+        ImageCache::new(DEFAULT_BUDGET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use std::time::Duration;
+
+    #[test]
+    fn test_image_cache() {
+        let dir = std::env::temp_dir().join("imgsampler-test-image-cache");
+        fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.png");
+        let b = dir.join("b.png");
+        RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]))
+            .save(&a)
+            .unwrap();
+        RgbaImage::from_pixel(4, 4, Rgba([0, 255, 0, 255]))
+            .save(&b)
+            .unwrap();
+
+        let mut cache = ImageCache::new(4 * 4 * 4 * 2);
+        let first = cache.get(&a).unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.get(&a).unwrap()));
+
+        // a changed file is decoded again
+        RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255]))
+            .save(&a)
+            .unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&a)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        let second = cache.get(&a).unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert_eq!(cache.len(), 1);
+
+        // two images fit, a third one pushes out the least recently used
+        cache.get(&b).unwrap();
+        cache.get(&a).unwrap();
+        assert_eq!(cache.used(), 4 * 4 * 4 * 2);
+        cache.set_budget(4 * 4 * 4);
+        assert_eq!(cache.len(), 1);
+        assert!(Arc::ptr_eq(&second, &cache.get(&a).unwrap()));
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert!(!Arc::ptr_eq(&second, &cache.get(&a).unwrap()));
+    }
+}
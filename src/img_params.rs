@@ -0,0 +1,54 @@
+use crate::parameter::Parameter;
+
+pub enum ImgParams {
+    Position(Box<dyn Parameter>, Box<dyn Parameter>),
+    Size(Box<dyn Parameter>, Box<dyn Parameter>),
+    Crop(
+        Box<dyn Parameter>,
+        Box<dyn Parameter>,
+        Box<dyn Parameter>,
+        Box<dyn Parameter>,
+    ),
+    Blur(Box<dyn Parameter>),
+    Opacity(Box<dyn Parameter>),
+    Brighten(Box<dyn Parameter>),
+    HueRot(Box<dyn Parameter>),
+    Contrast(Box<dyn Parameter>),
+    Scatter(Box<dyn Parameter>),
+    /// random walks added to the position, along x and along y
+    Brownian(Box<dyn Parameter>, Box<dyn Parameter>),
+}
+
+impl ImgParams {
+    /// The command this was built from.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImgParams::Position(..) => "pos",
+            ImgParams::Size(..) => "size",
+            ImgParams::Crop(..) => "crop",
+            ImgParams::Blur(_) => "blur",
+            ImgParams::Opacity(_) => "opacity",
+            ImgParams::Brighten(_) => "brighten",
+            ImgParams::HueRot(_) => "huerot",
+            ImgParams::Contrast(_) => "contrast",
+            ImgParams::Scatter(_) => "scatter",
+            ImgParams::Brownian(..) => "brownian",
+        }
+    }
+
+    /// The parameters, in argument order.
+    pub fn params_mut(&mut self) -> Vec<&mut Box<dyn Parameter>> {
+        match self {
+            ImgParams::Position(a, b) | ImgParams::Size(a, b) | ImgParams::Brownian(a, b) => {
+                vec![a, b]
+            }
+            ImgParams::Crop(a, b, c, d) => vec![a, b, c, d],
+            ImgParams::Blur(a)
+            | ImgParams::Opacity(a)
+            | ImgParams::Brighten(a)
+            | ImgParams::HueRot(a)
+            | ImgParams::Contrast(a)
+            | ImgParams::Scatter(a) => vec![a],
+        }
+    }
+}
@@ -0,0 +1,1372 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::ast::*;
+use crate::commands;
+use crate::diagnostic::Diagnostic;
+use crate::easing::{self, Easing};
+use crate::image_cache::ImageCache;
+use crate::img_params::ImgParams;
+use crate::layer::{self, Layer, ParamKey};
+use crate::line_parser;
+use crate::parameter::*;
+
+/// Everything a piece of code describes. Layers are kept in the order
+/// they appear in the code, which is also the order they're drawn in.
+#[derive(Default)]
+pub struct Evaluation {
+    pub layers: Vec<Layer>,
+    /// set by a `seed` statement, the last one wins
+    pub seed: Option<u64>,
+    /// set by a `bpm` statement, the last one wins
+    pub bpm: Option<f64>,
+    /// `let` statements, in order
+    pub variables: Vec<(String, Variable)>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Which copy of a repeated `img` line is being built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instance {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Default for Instance {
+    fn default() -> Self {
+        Instance { index: 0, count: 1 }
+    }
+}
+
+/// Parse and interpret `source`, loading images from `<asset_path>/images`
+/// through `cache`.
+pub fn evaluate(source: &str, asset_path: &Path, cache: &mut ImageCache) -> Evaluation {
+    evaluate_lines(source, 0..usize::MAX, asset_path, cache, &HashSet::new())
+}
+
+/// Like `evaluate`, but only looking at the 0-based line indices in `lines`.
+/// `defined` are the variables that can be used without a `let` in these
+/// lines, because they're already running.
+pub fn evaluate_lines(
+    source: &str,
+    lines: Range<usize>,
+    asset_path: &Path,
+    cache: &mut ImageCache,
+    defined: &HashSet<String>,
+) -> Evaluation {
+    let (script, diagnostics) = line_parser::parse_lines(source, lines);
+
+    let mut evaluation = Evaluation {
+        diagnostics,
+        ..Default::default()
+    };
+
+    // wherever it is, `reload` applies to all images
+    if script
+        .statements
+        .iter()
+        .any(|statement| statement.node == Statement::Reload)
+    {
+        cache.clear();
+    }
+
+    let mut defined = defined.clone();
+    for statement in script.statements.iter() {
+        match &statement.node {
+            Statement::Image(img) => eval_image(img, asset_path, cache, &defined, &mut evaluation),
+            Statement::Let(def) => eval_let(def, &mut defined, &mut evaluation),
+            Statement::Seed(seed) => evaluation.seed = Some(*seed),
+            Statement::Bpm(bpm) if *bpm > 0.0 => evaluation.bpm = Some(*bpm as f64),
+            Statement::Bpm(bpm) => evaluation.diagnostics.push(
+                Diagnostic::at(statement.span, "invalid tempo")
+                    .expected("more than 0 bpm")
+                    .found(bpm.to_string()),
+            ),
+            Statement::Reload => {}
+        }
+    }
+
+    evaluation
+}
+
+fn eval_let(def: &LetStatement, defined: &mut HashSet<String>, evaluation: &mut Evaluation) {
+    let param = check_variables(&def.value, defined)
+        .and_then(|_| build_param(&def.value, Instance::default()));
+    let param = match param {
+        Ok(param) => param,
+        Err(diag) => {
+            evaluation.diagnostics.push(diag);
+            return;
+        }
+    };
+
+    let name = def.name.node.clone();
+    defined.insert(name.clone());
+    // the last definition wins
+    evaluation
+        .variables
+        .retain(|(existing, _)| *existing != name);
+    evaluation
+        .variables
+        .push((name, Variable::new(param, def.value.node.to_string())));
+}
+
+fn eval_image(
+    img: &ImageStatement,
+    asset_path: &Path,
+    cache: &mut ImageCache,
+    defined: &HashSet<String>,
+    evaluation: &mut Evaluation,
+) {
+    let img_path = asset_path.join("images").join(&img.file.node);
+
+    let image = match cache.get(&img_path) {
+        Ok(image) => image,
+        Err(e) => {
+            evaluation.diagnostics.push(Diagnostic::at(
+                img.file.span,
+                format!("can't open image {}: {}", img_path.display(), e),
+            ));
+            return;
+        }
+    };
+
+    if let Some(repeat) = img.repeat.as_ref().filter(|repeat| repeat.node == 0) {
+        evaluation
+            .diagnostics
+            .push(Diagnostic::at(repeat.span, "no instances").expected("at least 1"));
+        return;
+    }
+    let count = img.repeat.as_ref().map_or(1, |repeat| repeat.node);
+
+    let mut layers = Vec::with_capacity(count);
+    for index in 0..count {
+        let instance = Instance { index, count };
+        let mut layer = Layer::new(img.layer_name().to_string(), image.clone());
+        layer.instance = index;
+
+        for command in img.commands.iter() {
+            let img_param = command
+                .node
+                .args
+                .iter()
+                .try_for_each(|arg| check_variables(arg, defined))
+                .and_then(|_| build_command(command, instance));
+            let mut img_param = match img_param {
+                Ok(img_param) => img_param,
+                Err(diag) => {
+                    // the same for every instance, only report it once
+                    if index == 0 {
+                        evaluation.diagnostics.push(diag);
+                    }
+                    continue;
+                }
+            };
+
+            // same numbering as `Layer::take_state_from` uses
+            let name = img_param.name();
+            let occurrence = layer
+                .effects
+                .iter()
+                .filter(|effect| effect.name() == name)
+                .count();
+            // `brownian` builds two parameters from one argument, both
+            // come from its source. `n` reads the same with any number of
+            // instances, so the count is part of the source where it's used.
+            let sources: Vec<String> = command
+                .node
+                .args
+                .iter()
+                .map(|expr| {
+                    if uses_count(expr) {
+                        format!("{} (n = {})", expr.node, count)
+                    } else {
+                        expr.node.to_string()
+                    }
+                })
+                .collect();
+            for arg in 0..img_param.params_mut().len() {
+                let key = ParamKey {
+                    command: name,
+                    occurrence,
+                    arg,
+                };
+                if let Some(source) = sources.get(arg).or(sources.last()) {
+                    layer.sources.insert(key, source.clone());
+                }
+            }
+
+            match img_param {
+                ImgParams::Position(..) => layer.position = Some(img_param),
+                ImgParams::Size(..) => layer.size = Some(img_param),
+                _ => layer.effects.push(img_param),
+            }
+        }
+
+        layers.push(layer);
+    }
+
+    // a later line with the same name (or alias) replaces the earlier one
+    layer::merge_layers(&mut evaluation.layers, layers);
+}
+
+pub fn build_command(
+    command: &Spanned<Command>,
+    instance: Instance,
+) -> Result<ImgParams, Diagnostic> {
+    let name = &command.node.name;
+    let args = &command.node.args;
+
+    let spec = commands::lookup(&name.node).ok_or_else(|| {
+        Diagnostic::at(name.span, "unknown command")
+            .expected(format!("one of {}", commands::names()))
+            .found(name.node.clone())
+    })?;
+
+    if args.len() != spec.arity() {
+        let span = args.get(spec.arity()).map_or(command.span, |arg| arg.span);
+        return Err(Diagnostic::at(
+            span,
+            format!(
+                "`{}` takes {} parameter(s), got {}",
+                spec.name,
+                spec.arity(),
+                args.len()
+            ),
+        )
+        .expected(format!("`{}`", spec.usage())));
+    }
+
+    let params = args
+        .iter()
+        .map(|arg| build_param(arg, instance))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((spec.build)(params))
+}
+
+pub fn build_param(
+    expr: &Spanned<ParamExpr>,
+    instance: Instance,
+) -> Result<Box<dyn Parameter>, Diagnostic> {
+    if expr.node.is_constant() {
+        return Ok(Box::new(StaticParameter::from_val(constant(
+            expr, instance,
+        )?)));
+    }
+
+    match &expr.node {
+        ParamExpr::Generator(gen) => {
+            let param = build_generator(gen, instance)?;
+            let param = match &gen.sync {
+                Some(sync) if sync.node > 0.0 => Box::new(SyncParameter::new(param, sync.node)),
+                Some(sync) => {
+                    return Err(Diagnostic::at(sync.span, "invalid subdivision")
+                        .expected("more than 0 steps per beat")
+                        .found(sync.node.to_string()))
+                }
+                None => param,
+            };
+            gen.modifiers.iter().try_fold(param, |param, modifier| {
+                build_modifier(param, modifier, instance)
+            })
+        }
+        ParamExpr::Var(name) => Ok(Box::new(VarParameter::new(name))),
+        ParamExpr::Neg(inner) => Ok(Box::new(NegParameter::new(build_param(inner, instance)?))),
+        ParamExpr::Binary(op, left, right) => Ok(Box::new(BinaryParameter::new(
+            *op,
+            build_param(left, instance)?,
+            build_param(right, instance)?,
+        ))),
+        ParamExpr::Scalar(_)
+        | ParamExpr::Duration(..)
+        | ParamExpr::Instance(_)
+        | ParamExpr::Keyword(_) => unreachable!("constant"),
+    }
+}
+
+/// Every `$name` in `expr` has to be defined by a `let` first.
+fn check_variables(expr: &Spanned<ParamExpr>, defined: &HashSet<String>) -> Result<(), Diagnostic> {
+    match &expr.node {
+        ParamExpr::Var(name) if !defined.contains(name) => {
+            let mut names: Vec<&str> = defined.iter().map(|name| name.as_str()).collect();
+            names.sort();
+            let diag = Diagnostic::at(expr.span, "unknown variable").found(format!("${}", name));
+            Err(if names.is_empty() {
+                diag.expected("a `let` before it")
+            } else {
+                diag.expected(format!("one of ${}", names.join(", $")))
+            })
+        }
+        ParamExpr::Generator(gen) => gen
+            .args
+            .iter()
+            .chain(
+                gen.modifiers
+                    .iter()
+                    .flat_map(|modifier| modifier.args.iter()),
+            )
+            .try_for_each(|arg| check_variables(arg, defined)),
+        ParamExpr::Neg(inner) => check_variables(inner, defined),
+        ParamExpr::Binary(_, left, right) => {
+            check_variables(left, defined)?;
+            check_variables(right, defined)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether `expr` depends on the number of instances, through `n`.
+fn uses_count(expr: &Spanned<ParamExpr>) -> bool {
+    match &expr.node {
+        ParamExpr::Instance(InstanceValue::Count) => true,
+        ParamExpr::Generator(gen) => gen
+            .args
+            .iter()
+            .chain(
+                gen.modifiers
+                    .iter()
+                    .flat_map(|modifier| modifier.args.iter()),
+            )
+            .any(uses_count),
+        ParamExpr::Neg(inner) => uses_count(inner),
+        ParamExpr::Binary(_, left, right) => uses_count(left) || uses_count(right),
+        _ => false,
+    }
+}
+
+/// The value of an expression that doesn't change from frame to frame.
+fn constant(expr: &Spanned<ParamExpr>, instance: Instance) -> Result<f32, Diagnostic> {
+    match &expr.node {
+        ParamExpr::Scalar(val) => Ok(*val),
+        ParamExpr::Duration(..) => Err(Diagnostic::at(expr.span, "duration not allowed here")
+            .expected("number")
+            .found(expr.node.to_string())),
+        ParamExpr::Keyword(word) => Err(Diagnostic::at(expr.span, "keyword not allowed here")
+            .expected("number")
+            .found(word.clone())),
+        ParamExpr::Instance(InstanceValue::Index) => Ok(instance.index as f32),
+        ParamExpr::Instance(InstanceValue::Count) => Ok(instance.count as f32),
+        ParamExpr::Neg(inner) => Ok(-constant(inner, instance)?),
+        ParamExpr::Binary(op, left, right) => {
+            Ok(op.apply(constant(left, instance)?, constant(right, instance)?))
+        }
+        ParamExpr::Generator(gen) => Err(Diagnostic::at(expr.span, "generator not allowed here")
+            .expected("number")
+            .found(gen.to_string())),
+        ParamExpr::Var(name) => Err(Diagnostic::at(expr.span, "variable not allowed here")
+            .expected("number")
+            .found(format!("${}", name))),
+    }
+}
+
+/// A number of steps, or a duration.
+fn period(expr: &Spanned<ParamExpr>, instance: Instance) -> Result<Period, Diagnostic> {
+    match expr.node {
+        ParamExpr::Duration(secs, TimeUnit::Seconds) => Ok(Period::Seconds(secs)),
+        ParamExpr::Duration(beats, TimeUnit::Beats) => Ok(Period::Beats(beats)),
+        _ => Ok(Period::Steps(constant(expr, instance)?)),
+    }
+}
+
+/// Like `period`, for lengths that can't be 0, which would divide by 0.
+fn positive_period(expr: &Spanned<ParamExpr>, instance: Instance) -> Result<Period, Diagnostic> {
+    check_positive(expr, period(expr, instance)?)
+}
+
+/// A lag of a frame or two is too short to notice, so unlike other lengths
+/// a plain number counts seconds, like in `lag 0.1`.
+fn lag_time(expr: &Spanned<ParamExpr>, instance: Instance) -> Result<Period, Diagnostic> {
+    let period = match expr.node {
+        ParamExpr::Duration(..) => period(expr, instance)?,
+        _ => Period::Seconds(constant(expr, instance)?),
+    };
+    check_positive(expr, period)
+}
+
+fn check_positive(expr: &Spanned<ParamExpr>, period: Period) -> Result<Period, Diagnostic> {
+    let (Period::Steps(length) | Period::Seconds(length) | Period::Beats(length)) = period;
+    if length <= 0.0 {
+        return Err(Diagnostic::at(expr.span, "invalid length")
+            .expected("more than 0")
+            .found(expr.node.to_string()));
+    }
+    Ok(period)
+}
+
+fn easing(expr: &Spanned<ParamExpr>) -> Result<Easing, Diagnostic> {
+    let unknown = || {
+        Diagnostic::at(expr.span, "unknown easing curve")
+            .expected(format!(
+                "linear, or in-, out- or in-out- followed by {}",
+                easing::CURVES
+            ))
+            .found(expr.node.to_string())
+    };
+    match &expr.node {
+        ParamExpr::Keyword(word) => Easing::from_name(word).ok_or_else(unknown),
+        _ => Err(unknown()),
+    }
+}
+
+/// Names of all generators, for error messages.
+const GENERATORS: &[&str] = &[
+    "ramp", "bounce", "ease", "tri", "saw", "isaw", "sine", "square", "noise", "noise2", "choose",
+    "wchoose", "shuffle", "cycle", "markov", "seq", "env", "walk",
+];
+
+fn build_generator(gen: &Generator, instance: Instance) -> Result<Box<dyn Parameter>, Diagnostic> {
+    // `<min> <max> <steps or duration> ...`
+    let range = || -> Result<(f32, f32, Period), Diagnostic> {
+        Ok(match gen.args.as_slice() {
+            [min, max, length, ..] => (
+                constant(min, instance)?,
+                constant(max, instance)?,
+                positive_period(length, instance)?,
+            ),
+            [min, max] => (
+                constant(min, instance)?,
+                constant(max, instance)?,
+                Period::Steps(6000.0),
+            ),
+            _ => (0.0, 1.0, Period::Steps(6000.0)),
+        })
+    };
+    let seq = || {
+        gen.args
+            .iter()
+            .map(|arg| constant(arg, instance))
+            .collect::<Result<Vec<f32>, _>>()
+    };
+    // whatever comes after the range
+    let extra = |idx: usize| -> Result<Option<f32>, Diagnostic> {
+        gen.args
+            .get(3 + idx)
+            .map(|arg| constant(arg, instance))
+            .transpose()
+    };
+
+    match gen.name.node.as_str() {
+        "bounce" => {
+            check_arity(gen, 4, "`[bounce <min> <max> <steps or duration> <curve>]`")?;
+            let (min, max, length) = range()?;
+            let param = Box::new(BounceParameter::with_period(min, max, length));
+            // `[bounce 0 1 120 ease-in-out-cubic]`
+            match gen.args.get(3) {
+                Some(curve) => Ok(Box::new(EaseParameter::new(
+                    param,
+                    easing(curve)?,
+                    min,
+                    max,
+                ))),
+                None => Ok(param),
+            }
+        }
+        // `[ramp 0 1 120 once ease-in-out-cubic]`, options in any order
+        "ramp" => {
+            let (min, max, length) = range()?;
+            let mut mode = None;
+            let mut curve = None;
+            for arg in gen.args.iter().skip(3) {
+                let word = match &arg.node {
+                    ParamExpr::Keyword(word) => word.as_str(),
+                    _ => "",
+                };
+                let found = match word {
+                    "once" => Some(RampMode::Once),
+                    "loop" => Some(RampMode::Loop),
+                    "pingpong" => Some(RampMode::PingPong),
+                    _ => None,
+                };
+                if let Some(found) = found {
+                    if mode.replace(found).is_some() {
+                        return Err(Diagnostic::at(arg.span, "more than one ramp mode")
+                            .expected("one of once, loop or pingpong")
+                            .found(arg.node.to_string()));
+                    }
+                } else if let Some(found) = Easing::from_name(word) {
+                    if curve.replace(found).is_some() {
+                        return Err(Diagnostic::at(arg.span, "more than one easing curve")
+                            .expected("a single easing curve")
+                            .found(arg.node.to_string()));
+                    }
+                } else {
+                    return Err(Diagnostic::at(arg.span, "unknown ramp option")
+                        .expected(format!(
+                            "once, loop, pingpong or an easing curve like in-out-{}",
+                            easing::CURVES
+                        ))
+                        .found(arg.node.to_string()));
+                }
+            }
+            let mode = mode.unwrap_or(RampMode::Loop);
+            let param = Box::new(RampParameter::with_mode(min, max, length, mode));
+            match curve {
+                Some(curve) => Ok(Box::new(EaseParameter::new(param, curve, min, max))),
+                None => Ok(param),
+            }
+        }
+        "ease" => match gen.args.as_slice() {
+            [curve, param] => Ok(Box::new(EaseParameter::new(
+                build_param(param, instance)?,
+                easing(curve)?,
+                0.0,
+                1.0,
+            ))),
+            _ => Err(Diagnostic::at(gen.name.span, "`ease` takes 2 arguments")
+                .expected("`[ease <curve> <param>]`")),
+        },
+        "tri" | "saw" | "isaw" | "sine" => {
+            check_arity(
+                gen,
+                4,
+                &format!(
+                    "`[{} <min> <max> <steps or duration> <phase>]`",
+                    gen.name.node
+                ),
+            )?;
+            let (min, max, length) = range()?;
+            let shape = match gen.name.node.as_str() {
+                "tri" => Shape::Triangle,
+                "saw" => Shape::Saw,
+                "isaw" => Shape::InvSaw,
+                _ => Shape::Sine,
+            };
+            let phase = extra(0)?.unwrap_or(0.0);
+            Ok(Box::new(LfoParameter::new(shape, min, max, length, phase)))
+        }
+        "square" => {
+            check_arity(
+                gen,
+                5,
+                "`[square <min> <max> <steps or duration> <duty> <phase>]`",
+            )?;
+            let (min, max, length) = range()?;
+            let duty = extra(0)?.unwrap_or(0.5);
+            if !(0.0..=1.0).contains(&duty) {
+                let arg = &gen.args[3];
+                return Err(Diagnostic::at(arg.span, "invalid duty cycle")
+                    .expected("between 0 and 1")
+                    .found(arg.node.to_string()));
+            }
+            let phase = extra(1)?.unwrap_or(0.0);
+            Ok(Box::new(LfoParameter::new(
+                Shape::Square(duty),
+                min,
+                max,
+                length,
+                phase,
+            )))
+        }
+        "noise" => {
+            check_arity(gen, 3, "`[noise <min> <max> <steps or duration>]`")?;
+            let (min, max, length) = range()?;
+            Ok(Box::new(NoiseParameter::new(min, max, length)))
+        }
+        "noise2" => {
+            check_arity(gen, 4, "`[noise2 <min> <max> <steps or duration> <y>]`")?;
+            let (min, max, length) = range()?;
+            let y = extra(0)?.unwrap_or(0.0);
+            Ok(Box::new(NoiseParameter::field(min, max, length, y)))
+        }
+        "choose" | "wchoose" | "shuffle" | "cycle" if gen.args.is_empty() => {
+            Err(Diagnostic::at(gen.name.span, "generator without values")
+                .expected("at least one number"))
+        }
+        // `[choose 1 2 3 norepeat]`
+        "choose" => match gen.args.split_last() {
+            Some((last, items)) if matches!(&last.node, ParamExpr::Keyword(word) if word == "norepeat") =>
+            {
+                let items = items
+                    .iter()
+                    .map(|arg| constant(arg, instance))
+                    .collect::<Result<Vec<f32>, _>>()?;
+                if items.is_empty() {
+                    return Err(Diagnostic::at(last.span, "generator without values")
+                        .expected("at least one number"));
+                }
+                Ok(Box::new(ChooseParameter::no_repeat(&items)))
+            }
+            _ => Ok(Box::new(ChooseParameter::from_seq(&seq()?))),
+        },
+        "wchoose" => {
+            let values = seq()?;
+            if !values.len().is_multiple_of(2) {
+                return Err(Diagnostic::at(gen.name.span, "value without a weight")
+                    .expected("`[wchoose <value> <weight> ...]`"));
+            }
+            let pairs: Vec<(f32, f32)> = values.chunks(2).map(|pair| (pair[0], pair[1])).collect();
+            if let Some((i, _)) = pairs
+                .iter()
+                .enumerate()
+                .find(|(_, (_, weight))| *weight < 0.0)
+            {
+                let arg = &gen.args[2 * i + 1];
+                return Err(Diagnostic::at(arg.span, "negative weight")
+                    .expected("0 or more")
+                    .found(arg.node.to_string()));
+            }
+            if pairs.iter().all(|(_, weight)| *weight == 0.0) {
+                return Err(Diagnostic::at(gen.name.span, "all weights are 0")
+                    .expected("at least one weight above 0"));
+            }
+            Ok(Box::new(WeightedChooseParameter::from_pairs(&pairs)))
+        }
+        "shuffle" => Ok(Box::new(ShuffleParameter::from_seq(&seq()?))),
+        "markov" => build_markov(gen, instance),
+        "seq" => build_seq(gen, instance),
+        "env" => build_env(gen, instance),
+        // `[walk <start> <min> <max> <step>]`, optionally `reflect` or `wrap`
+        "walk" => {
+            let usage = "`[walk <start> <min> <max> <step> (reflect or wrap)]`";
+            let (values, edge) = match gen.args.split_at(gen.args.len().min(4)) {
+                (values, []) => (values, Edge::Reflect),
+                (values, [edge]) if is_keyword(edge, "reflect") => (values, Edge::Reflect),
+                (values, [edge]) if is_keyword(edge, "wrap") => (values, Edge::Wrap),
+                (_, [edge, ..]) => {
+                    return Err(Diagnostic::at(edge.span, "unknown edge")
+                        .expected("reflect or wrap")
+                        .found(edge.node.to_string()))
+                }
+            };
+            let [start, min, max, step] = values else {
+                return Err(
+                    Diagnostic::at(gen.name.span, "`walk` takes 4 arguments").expected(usage)
+                );
+            };
+            Ok(Box::new(WalkParameter::new(
+                constant(start, instance)?,
+                constant(min, instance)?,
+                constant(max, instance)?,
+                Box::new(StaticParameter::from_val(constant(step, instance)?)),
+                edge,
+            )))
+        }
+        "cycle" => Ok(Box::new(CycleParameter::from_seq(&seq()?))),
+        other => Err(Diagnostic::at(gen.name.span, "unknown generator")
+            .expected(format!("one of {}", GENERATORS.join(", ")))
+            .found(other)),
+    }
+}
+
+/// Rejects whatever comes after the last argument `gen` takes.
+fn check_arity(gen: &Generator, max: usize, usage: &str) -> Result<(), Diagnostic> {
+    match gen.args.get(max) {
+        Some(arg) => Err(Diagnostic::at(
+            arg.span,
+            format!(
+                "`{}` takes up to {} arguments, got {}",
+                gen.name.node,
+                max,
+                gen.args.len()
+            ),
+        )
+        .expected(usage)
+        .found(arg.node.to_string())),
+        None => Ok(()),
+    }
+}
+
+fn is_keyword(expr: &Spanned<ParamExpr>, keyword: &str) -> bool {
+    matches!(&expr.node, ParamExpr::Keyword(word) if word == keyword)
+}
+
+/// `[markov from 0 100 1 200 3 from 100 0 1 from 200 0 1]`, each `from`
+/// followed by a value and its successors with their weights.
+fn build_markov(gen: &Generator, instance: Instance) -> Result<Box<dyn Parameter>, Diagnostic> {
+    let usage = "`[markov from <value> <next> <weight> ... from ...]`";
+    if gen.args.is_empty() {
+        return Err(
+            Diagnostic::at(gen.name.span, "generator without steps").expected("at least one step")
+        );
+    }
+
+    // split at each `from`
+    let mut chains: Vec<&[Spanned<ParamExpr>]> = Vec::new();
+    let mut rest = gen.args.as_slice();
+    while let Some((from, args)) = rest.split_first() {
+        if !is_keyword(from, "from") {
+            return Err(Diagnostic::at(from.span, "expected `from`")
+                .expected(usage)
+                .found(from.node.to_string()));
+        }
+        let end = args
+            .iter()
+            .position(|arg| is_keyword(arg, "from"))
+            .unwrap_or(args.len());
+        if end == 0 {
+            return Err(Diagnostic::at(from.span, "`from` without a value").expected(usage));
+        }
+        if end.is_multiple_of(2) {
+            return Err(
+                Diagnostic::at(args[end - 1].span, "successor without a weight").expected(usage),
+            );
+        }
+        chains.push(&args[..end]);
+        rest = &args[end..];
+    }
+
+    let mut values = Vec::with_capacity(chains.len());
+    for chain in chains.iter() {
+        let value = constant(&chain[0], instance)?;
+        if values.contains(&value) {
+            return Err(Diagnostic::at(chain[0].span, "value listed twice")
+                .expected("one `from` per value")
+                .found(chain[0].node.to_string()));
+        }
+        values.push(value);
+    }
+
+    let mut successors = Vec::with_capacity(chains.len());
+    for chain in chains.iter() {
+        let mut next = Vec::new();
+        for pair in chain[1..].chunks(2) {
+            let value = constant(&pair[0], instance)?;
+            let state = values.iter().position(|v| *v == value).ok_or_else(|| {
+                Diagnostic::at(pair[0].span, "value without a `from`")
+                    .expected(format!("a `from {}`", pair[0].node))
+            })?;
+            let weight = constant(&pair[1], instance)?;
+            if weight < 0.0 {
+                return Err(Diagnostic::at(pair[1].span, "negative weight")
+                    .expected("0 or more")
+                    .found(pair[1].node.to_string()));
+            }
+            next.push((state, weight));
+        }
+        successors.push(next);
+    }
+
+    Ok(Box::new(MarkovParameter::new(&values, &successors)))
+}
+
+/// `[seq 0 4 100 2 rest 2 200 1b]`, values and how long each one lasts,
+/// `rest` holds the previous value.
+fn build_seq(gen: &Generator, instance: Instance) -> Result<Box<dyn Parameter>, Diagnostic> {
+    if gen.args.is_empty() {
+        return Err(
+            Diagnostic::at(gen.name.span, "generator without steps").expected("at least one step")
+        );
+    }
+    if !gen.args.len().is_multiple_of(2) {
+        return Err(Diagnostic::at(gen.name.span, "step without a length")
+            .expected("`[seq <value or rest> <steps or duration> ...]`"));
+    }
+    let steps = gen
+        .args
+        .chunks(2)
+        .map(|step| {
+            let value = if is_keyword(&step[0], "rest") {
+                None
+            } else {
+                Some(constant(&step[0], instance)?)
+            };
+            Ok((value, positive_period(&step[1], instance)?))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Box::new(SeqParameter::new(&steps)))
+}
+
+/// `[env <attack> <decay> <sustain> <release> <trigger>]`, where the
+/// trigger is `key <name>`, `beat <beats>` or `above <param> <threshold>`.
+fn build_env(gen: &Generator, instance: Instance) -> Result<Box<dyn Parameter>, Diagnostic> {
+    let triggers = "`key <name>`, `beat <beats>` or `above <param> <threshold>`";
+    let (stages, trigger) = match gen.args.as_slice() {
+        [a, d, s, r, trigger @ ..] if !trigger.is_empty() => ([a, d, s, r], trigger),
+        _ => {
+            return Err(
+                Diagnostic::at(gen.name.span, "envelope without a trigger").expected(format!(
+                    "`[env <attack> <decay> <sustain> <release> <trigger>]`, the trigger is {}",
+                    triggers
+                )),
+            )
+        }
+    };
+
+    let length = |expr: &Spanned<ParamExpr>| {
+        let length = period(expr, instance)?;
+        let (Period::Steps(len) | Period::Seconds(len) | Period::Beats(len)) = length;
+        if len < 0.0 {
+            return Err(Diagnostic::at(expr.span, "negative length")
+                .expected("0 or more")
+                .found(expr.node.to_string()));
+        }
+        Ok(length)
+    };
+    let attack = length(stages[0])?;
+    let decay = length(stages[1])?;
+    let sustain = constant(stages[2], instance)?;
+    let release = length(stages[3])?;
+
+    let trigger = match trigger {
+        [kind, key] if is_keyword(kind, "key") => match &key.node {
+            ParamExpr::Keyword(key) => Trigger::Key(key.clone()),
+            // `n` reads as the number of instances everywhere else
+            ParamExpr::Instance(_) => Trigger::Key(key.node.to_string()),
+            _ => {
+                return Err(Diagnostic::at(key.span, "invalid key")
+                    .expected("a key like `a` or `space`")
+                    .found(key.node.to_string()))
+            }
+        },
+        [kind, beats] if is_keyword(kind, "beat") => match constant(beats, instance)? {
+            beats if beats > 0.0 => Trigger::Beat(beats),
+            _ => {
+                return Err(Diagnostic::at(beats.span, "invalid number of beats")
+                    .expected("more than 0")
+                    .found(beats.node.to_string()))
+            }
+        },
+        [kind, param, threshold] if is_keyword(kind, "above") => Trigger::Above(
+            build_param(param, instance)?,
+            constant(threshold, instance)?,
+        ),
+        [kind, ..] => {
+            return Err(Diagnostic::at(kind.span, "invalid trigger")
+                .expected(triggers)
+                .found(kind.node.to_string()))
+        }
+        [] => unreachable!("checked above"),
+    };
+
+    Ok(Box::new(EnvParameter::new(
+        attack, decay, sustain, release, trigger,
+    )))
+}
+
+/// Names of all modifiers, for error messages.
+const MODIFIERS: &[&str] = &["clamp", "quant", "lag", "smooth", "hold"];
+
+/// Wraps `param` in whatever `modifier` names, i.e. `quant 50`.
+fn build_modifier(
+    param: Box<dyn Parameter>,
+    modifier: &Modifier,
+    instance: Instance,
+) -> Result<Box<dyn Parameter>, Diagnostic> {
+    let name = &modifier.name;
+    let (arity, usage) = match name.node.as_str() {
+        "clamp" => (2, "`clamp <min> <max>`"),
+        "quant" => (1, "`quant <step>`"),
+        "lag" | "smooth" => (1, "`lag <seconds or duration>`"),
+        "hold" => (1, "`hold <steps or duration>`"),
+        other => {
+            return Err(Diagnostic::at(name.span, "unknown modifier")
+                .expected(format!("one of {}", MODIFIERS.join(", ")))
+                .found(other))
+        }
+    };
+    if modifier.args.len() != arity {
+        let span = modifier.args.get(arity).map_or(name.span, |arg| arg.span);
+        return Err(Diagnostic::at(
+            span,
+            format!(
+                "`{}` takes {} argument(s), got {}",
+                name.node,
+                arity,
+                modifier.args.len()
+            ),
+        )
+        .expected(usage));
+    }
+
+    let args = &modifier.args;
+    Ok(match name.node.as_str() {
+        "clamp" => Box::new(ClampParameter::new(
+            param,
+            constant(&args[0], instance)?,
+            constant(&args[1], instance)?,
+        )),
+        "quant" => Box::new(QuantParameter::new(param, constant(&args[0], instance)?)),
+        "lag" | "smooth" => Box::new(LagParameter::new(param, lag_time(&args[0], instance)?)),
+        "hold" => Box::new(HoldParameter::new(
+            param,
+            positive_period(&args[0], instance)?,
+        )),
+        _ => unreachable!("checked above"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(line: &str) -> Spanned<Command> {
+        match line_parser::parse_line(1, line).unwrap().node {
+            Statement::Image(mut img) => img.commands.remove(0),
+            _ => panic!("expected img statement"),
+        }
+    }
+
+    #[test]
+    fn test_build_command() {
+        assert!(matches!(
+            build_command(
+                &command("img a.jpg pos 0 [bounce 0 1 20]"),
+                Instance::default()
+            ),
+            Ok(ImgParams::Position(..))
+        ));
+
+        let diag = build_command(&command("img a.jpg pos [bouce 0 1] 0"), Instance::default())
+            .err()
+            .unwrap();
+        assert_eq!(diag.column, 16);
+        assert_eq!(diag.found.as_deref(), Some("bouce"));
+
+        let diag = build_command(&command("img a.jpg crop 0 0 1"), Instance::default())
+            .err()
+            .unwrap();
+        assert_eq!(diag.column, 11);
+        assert_eq!(
+            diag.expected.as_deref(),
+            Some("`crop <x:fraction> <y:fraction> <w:fraction> <h:fraction>`")
+        );
+
+        let diag = build_command(&command("img a.jpg blur 1 2"), Instance::default())
+            .err()
+            .unwrap();
+        assert_eq!(diag.column, 18);
+
+        assert!(build_command(
+            &command("img a.jpg pos [square 0 1 50 0.25] [sine -1 1 2b 0.5]"),
+            Instance::default()
+        )
+        .is_ok());
+
+        for (line, message, column) in [
+            (
+                "img a.jpg blur [sine 0 1 2s 0.5 junk 7]",
+                "`sine` takes up to 4 arguments, got 6",
+                33,
+            ),
+            (
+                "img a.jpg blur [bounce 0 1 4 linear 9]",
+                "`bounce` takes up to 4 arguments, got 5",
+                37,
+            ),
+            (
+                "img a.jpg blur [square 0 1 4 0.5 0 1]",
+                "`square` takes up to 5 arguments, got 6",
+                36,
+            ),
+            (
+                "img a.jpg blur [square 0 1 4 1.5]",
+                "invalid duty cycle",
+                30,
+            ),
+        ] {
+            let diag = build_command(&command(line), Instance::default())
+                .err()
+                .unwrap();
+            assert_eq!(diag.message, message, "{}", line);
+            assert_eq!(diag.column, column, "{}", line);
+        }
+
+        let diag = build_command(&command("img a.jpg blurr 2"), Instance::default())
+            .err()
+            .unwrap();
+        assert_eq!(diag.found.as_deref(), Some("blurr"));
+    }
+
+    #[test]
+    fn test_build_expression() {
+        let mut ctx = Context::new(0);
+        let Ok(ImgParams::Position(mut x, mut y)) = build_command(
+            &command("img a.jpg pos [ramp 0 10 10] * 2 + 1 -(2 + [cycle 1 2]) / 2"),
+            Instance::default(),
+        ) else {
+            panic!("expected position");
+        };
+        let xs: Vec<f32> = (0..3).map(|_| x.get_next(&mut ctx)).collect();
+        assert_eq!(xs, [1.0, 3.0, 5.0]);
+        let ys: Vec<f32> = (0..2).map(|_| y.get_next(&mut ctx)).collect();
+        assert_eq!(ys, [-1.5, -2.0]);
+
+        // constant generator arguments can be computed
+        let instance = Instance { index: 2, count: 4 };
+        let Ok(ImgParams::Blur(mut blur)) = build_command(
+            &command("img a.jpg blur [cycle (index * 10) (n / 0)]"),
+            instance,
+        ) else {
+            panic!("expected blur");
+        };
+        assert_eq!(blur.get_next(&mut ctx), 20.0);
+        assert_eq!(blur.get_next(&mut ctx), 0.0);
+
+        // the first instance would get a length of 0
+        for line in [
+            "img a.jpg blur [bounce -300 300 index]",
+            "img a.jpg blur [ramp 0 1 0]",
+            "img a.jpg blur [sine 0 1 -1s]",
+            "img a.jpg blur [noise 0 1 0b]",
+        ] {
+            let diag = build_command(&command(line), Instance { index: 0, count: 8 })
+                .err()
+                .unwrap();
+            assert_eq!(diag.message, "invalid length", "{}", line);
+        }
+
+        let diag = build_command(
+            &command("img a.jpg blur [ramp 0 [bounce 0 1] 10]"),
+            Instance::default(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(diag.column, 24);
+    }
+
+    #[test]
+    fn test_build_choices() {
+        for line in [
+            "img a.jpg blur [choose 1 2 3 norepeat]",
+            "img a.jpg blur [wchoose 1 0.5 2 0.25]",
+            "img a.jpg blur [shuffle 1 2 3]",
+        ] {
+            assert!(
+                build_command(&command(line), Instance::default()).is_ok(),
+                "{}",
+                line
+            );
+        }
+
+        let diag = build_command(
+            &command("img a.jpg blur [wchoose 1 0.5 2]"),
+            Instance::default(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(diag.message, "value without a weight");
+        let diag = build_command(
+            &command("img a.jpg blur [wchoose 1 1 2 -1]"),
+            Instance::default(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(diag.column, 31);
+        let diag = build_command(
+            &command("img a.jpg blur [choose norepeat]"),
+            Instance::default(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(diag.message, "generator without values");
+    }
+
+    #[test]
+    fn test_build_sequences() {
+        let mut ctx = Context::new(0);
+        let Ok(ImgParams::Position(mut x, mut y)) = build_command(
+            &command("img a.jpg pos [markov from 0 10 1 from 10 0 1] [seq 1 2 rest 1 3 1]"),
+            Instance::default(),
+        ) else {
+            panic!("expected position");
+        };
+        let xs: Vec<f32> = (0..4).map(|_| x.get_next(&mut ctx)).collect();
+        assert_eq!(xs, [0.0, 10.0, 0.0, 10.0]);
+        let ys: Vec<f32> = (0..5).map(|_| y.get_next(&mut ctx)).collect();
+        assert_eq!(ys, [1.0, 1.0, 1.0, 3.0, 1.0]);
+
+        for (line, message) in [
+            ("img a.jpg blur [markov 0 10 1]", "expected `from`"),
+            (
+                "img a.jpg blur [markov from 0 10]",
+                "successor without a weight",
+            ),
+            (
+                "img a.jpg blur [markov from 0 10 1]",
+                "value without a `from`",
+            ),
+            (
+                "img a.jpg blur [markov from 0 0 1 from 0 0 1]",
+                "value listed twice",
+            ),
+            ("img a.jpg blur [seq 1 2 3]", "step without a length"),
+            ("img a.jpg blur [seq 1 0]", "invalid length"),
+            ("img a.jpg blur [markov]", "generator without steps"),
+            ("img a.jpg blur [seq | lag 1]", "generator without steps"),
+        ] {
+            let diag = build_command(&command(line), Instance::default())
+                .err()
+                .unwrap();
+            assert_eq!(diag.message, message, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_build_env() {
+        let mut ctx = Context::new(0);
+        let Ok(ImgParams::Opacity(mut opacity)) = build_command(
+            &command("img a.jpg opacity [env 1 0 0.5 1 key space]"),
+            Instance::default(),
+        ) else {
+            panic!("expected opacity");
+        };
+        assert_eq!(opacity.get_next(&mut ctx), 0.0);
+        ctx.press_key("space");
+        assert_eq!(opacity.get_next(&mut ctx), 0.0);
+        assert_eq!(opacity.get_next(&mut ctx), 0.5);
+
+        // `n` is the key here, not the number of instances
+        let Ok(ImgParams::Blur(mut blur)) = build_command(
+            &command("img a.jpg blur [env 0 0 1 0 key n]"),
+            Instance { index: 0, count: 3 },
+        ) else {
+            panic!("expected blur");
+        };
+        ctx.press_key("n");
+        assert_eq!(blur.get_next(&mut ctx), 1.0);
+
+        for line in [
+            "img a.jpg blur [env 1s 1s 1 1b beat 4]",
+            "img a.jpg blur [env 0 0 1 0 key n]",
+            "img a.jpg blur [env 0 0 1 10 above [sine 0 1 2b] 0.9]",
+        ] {
+            assert!(
+                build_command(&command(line), Instance::default()).is_ok(),
+                "{}",
+                line
+            );
+        }
+        for (line, message) in [
+            ("img a.jpg blur [env 1 1 1 1]", "envelope without a trigger"),
+            ("img a.jpg blur [env 1 1 1 1 key 4]", "invalid key"),
+            (
+                "img a.jpg blur [env 1 1 1 1 beat 0]",
+                "invalid number of beats",
+            ),
+            ("img a.jpg blur [env 1 1 1 1 when 1]", "invalid trigger"),
+            ("img a.jpg blur [env -1 1 1 1 key a]", "negative length"),
+        ] {
+            let diag = build_command(&command(line), Instance::default())
+                .err()
+                .unwrap();
+            assert_eq!(diag.message, message, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_build_modifiers() {
+        let mut ctx = Context::new(0);
+        let Ok(ImgParams::Position(mut x, mut y)) = build_command(
+            &command("img a.jpg pos [ramp 0 10 10 | quant 4 | clamp 0 6] [cycle 1 2 3 | hold 2]"),
+            Instance::default(),
+        ) else {
+            panic!("expected position");
+        };
+        let xs: Vec<f32> = (0..11).map(|_| x.get_next(&mut ctx)).collect();
+        assert_eq!(xs, [0.0, 0.0, 4.0, 4.0, 4.0, 4.0, 6.0, 6.0, 6.0, 6.0, 6.0]);
+        let ys: Vec<f32> = (0..5).map(|_| y.get_next(&mut ctx)).collect();
+        assert_eq!(ys, [1.0, 1.0, 3.0, 3.0, 2.0]);
+
+        // a plain number is seconds, enough to see at 24 fps
+        let Ok(ImgParams::Blur(mut blur)) = build_command(
+            &command("img a.jpg blur [cycle 0 1 1 | lag 0.1]"),
+            Instance::default(),
+        ) else {
+            panic!("expected blur");
+        };
+        let mut lagged = Vec::new();
+        for _ in 0..3 {
+            lagged.push(blur.get_next(&mut ctx));
+            ctx.clock.advance(1.0 / 24.0);
+        }
+        assert_eq!(lagged[0], 0.0);
+        assert!(lagged[1] > 0.2 && lagged[1] < 0.5, "{:?}", lagged);
+        assert!(lagged[2] > lagged[1] && lagged[2] < 1.0, "{:?}", lagged);
+        for line in [
+            "img a.jpg blur [ramp 0 1 | lag -1]",
+            "img a.jpg blur [ramp 0 1 | smooth 0ms]",
+            "img a.jpg blur [ramp 0 1 | hold 0]",
+            "img a.jpg blur [ramp 0 1 | hold 0s]",
+            "img a.jpg blur [ramp 0 1 | hold -1s]",
+        ] {
+            let diag = build_command(&command(line), Instance::default())
+                .err()
+                .unwrap();
+            assert_eq!(diag.message, "invalid length", "{}", line);
+        }
+
+        assert!(build_command(
+            &command("img a.jpg blur [choose 0 10 /4 | lag 200ms | smooth 3]"),
+            Instance::default()
+        )
+        .is_ok());
+        let diag = build_command(
+            &command("img a.jpg blur [ramp 0 1 | wobble]"),
+            Instance::default(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(diag.message, "unknown modifier");
+        assert_eq!(diag.column, 28);
+        let diag = build_command(
+            &command("img a.jpg blur [ramp 0 1 | clamp 0]"),
+            Instance::default(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(diag.expected.as_deref(), Some("`clamp <min> <max>`"));
+    }
+
+    #[test]
+    fn test_build_ramp_modes() {
+        let mut ctx = Context::new(0);
+        let Ok(ImgParams::Position(mut x, mut y)) = build_command(
+            &command("img a.jpg pos [ramp 0 2 2 once] [ramp 0 4 4 in-quad pingpong]"),
+            Instance::default(),
+        ) else {
+            panic!("expected position");
+        };
+        let xs: Vec<f32> = (0..5).map(|_| x.get_next(&mut ctx)).collect();
+        assert_eq!(xs, [0.0, 1.0, 2.0, 2.0, 2.0]);
+        let ys: Vec<f32> = (0..7).map(|_| y.get_next(&mut ctx)).collect();
+        assert_eq!(ys, [0.0, 0.25, 1.0, 2.25, 4.0, 2.25, 1.0]);
+
+        let diag = build_command(
+            &command("img a.jpg blur [ramp 0 1 4 twice]"),
+            Instance::default(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(diag.message, "unknown ramp option");
+        assert_eq!(diag.found.as_deref(), Some("twice"));
+        for (line, message, found) in [
+            (
+                "img a.jpg blur [ramp 0 1 4 once loop]",
+                "more than one ramp mode",
+                "loop",
+            ),
+            (
+                "img a.jpg blur [ramp 0 1 4 in-quad once out-cubic]",
+                "more than one easing curve",
+                "out-cubic",
+            ),
+        ] {
+            let diag = build_command(&command(line), Instance::default())
+                .err()
+                .unwrap();
+            assert_eq!(diag.message, message, "{}", line);
+            assert_eq!(diag.found.as_deref(), Some(found), "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_build_walk() {
+        let mut ctx = Context::new(0);
+        let Ok(ImgParams::HueRot(mut hue)) = build_command(
+            &command("img a.jpg huerot [walk 0 0 360 30 wrap]"),
+            Instance::default(),
+        ) else {
+            panic!("expected huerot");
+        };
+        let values: Vec<f32> = (0..100).map(|_| hue.get_next(&mut ctx)).collect();
+        assert_eq!(values[0], 0.0);
+        assert!(values.iter().all(|v| (0.0..=360.0).contains(v)));
+
+        // strays no more than 25 steps from the position
+        let Ok(ImgParams::Brownian(mut x, mut y)) =
+            build_command(&command("img a.jpg brownian 5"), Instance::default())
+        else {
+            panic!("expected brownian");
+        };
+        let mut offsets = Vec::new();
+        for _ in 0..20000 {
+            offsets.push(x.get_next(&mut ctx));
+            offsets.push(y.get_next(&mut ctx));
+            ctx.clock.advance(1.0 / 24.0);
+        }
+        assert!(offsets.iter().all(|offset| offset.abs() <= 125.0));
+        assert!(offsets.iter().any(|offset| offset.abs() > 50.0));
+        for (line, message) in [
+            ("img a.jpg blur [walk 0 0 10]", "`walk` takes 4 arguments"),
+            ("img a.jpg blur [walk 0 0 10 1 bounce]", "unknown edge"),
+        ] {
+            let diag = build_command(&command(line), Instance::default())
+                .err()
+                .unwrap();
+            assert_eq!(diag.message, message, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_build_easing() {
+        let mut ctx = Context::new(0);
+        let Ok(ImgParams::Position(mut x, mut y)) = build_command(
+            &command("img a.jpg pos [ramp 0 100 4 in-out-cubic] [ease in-quad [ramp 0 1 2]]"),
+            Instance::default(),
+        ) else {
+            panic!("expected position");
+        };
+        let xs: Vec<f32> = (0..5).map(|_| x.get_next(&mut ctx)).collect();
+        assert_eq!(xs, [0.0, 6.25, 50.0, 93.75, 100.0]);
+        let ys: Vec<f32> = (0..3).map(|_| y.get_next(&mut ctx)).collect();
+        assert_eq!(ys, [0.0, 0.25, 1.0]);
+
+        let diag = build_command(
+            &command("img a.jpg blur [ramp 0 1 4 in-out-cubes]"),
+            Instance::default(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(diag.column, 28);
+        assert_eq!(diag.found.as_deref(), Some("in-out-cubes"));
+        let diag = build_command(
+            &command("img a.jpg blur [bounce 0 1 4 in-out-cubes]"),
+            Instance::default(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(diag.message, "unknown easing curve");
+
+        let diag = build_command(&command("img a.jpg blur [ramp 0 x]"), Instance::default())
+            .err()
+            .unwrap();
+        assert_eq!(diag.message, "keyword not allowed here");
+    }
+
+    #[test]
+    fn test_readme_examples() {
+        let mut in_code = false;
+        for (i, line) in include_str!("../README.md").lines().enumerate() {
+            if line.starts_with("```") {
+                in_code = !in_code;
+                continue;
+            }
+            // skip the shell command under "Rendering to disk"
+            if !in_code || line.is_empty() || line.starts_with("imgsampler ") {
+                continue;
+            }
+            let statement = line_parser::parse_line(i + 1, line)
+                .unwrap_or_else(|diag| panic!("README.md:{}", diag));
+            let built = match statement.node {
+                Statement::Image(img) => {
+                    let count = img.repeat.map_or(1, |repeat| repeat.node);
+                    (0..count).try_for_each(|index| {
+                        img.commands.iter().try_for_each(|command| {
+                            build_command(command, Instance { index, count }).map(|_| ())
+                        })
+                    })
+                }
+                Statement::Let(def) => build_param(&def.value, Instance::default()).map(|_| ()),
+                _ => Ok(()),
+            };
+            if let Err(diag) = built {
+                panic!("README.md:{}", diag);
+            }
+        }
+    }
+
+    #[test]
+    fn test_missing_image() {
+        let evaluation = evaluate(
+            "\nimg does-not-exist.jpg pos 0 0",
+            Path::new("/nonexistent"),
+            &mut ImageCache::default(),
+        );
+        assert!(evaluation.layers.is_empty());
+        assert_eq!(evaluation.diagnostics.len(), 1);
+        assert_eq!(evaluation.diagnostics[0].line, 2);
+        assert_eq!(evaluation.diagnostics[0].column, 5);
+    }
+}
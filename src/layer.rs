@@ -0,0 +1,202 @@
+use image::{DynamicImage, GenericImageView, Pixel};
+use rand::Rng;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::img_params::ImgParams;
+use crate::parameter::{Context, Parameter};
+
+/// Identifies a parameter within a layer: the second `blur` command's
+/// first argument is `{ command: "blur", occurrence: 1, arg: 0 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParamKey {
+    pub command: &'static str,
+    pub occurrence: usize,
+    pub arg: usize,
+}
+
+/// An image placed on the canvas, along with everything that animates it.
+pub struct Layer {
+    pub name: String,
+    /// which copy of a repeated `img` line this is, 0 if it isn't repeated
+    pub instance: usize,
+    pub image: Arc<DynamicImage>,
+    pub position: Option<ImgParams>,
+    pub size: Option<ImgParams>,
+    pub effects: Vec<ImgParams>,
+    /// normalized source of every parameter, to tell if it changed
+    pub sources: HashMap<ParamKey, String>,
+}
+
+/// One processed image, ready to be drawn centered at `x`/`y`
+/// (origin at canvas center, y up) and scaled to `w`x`h`.
+pub struct Stamp {
+    pub image: DynamicImage,
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Layer {
+    pub fn new(name: String, image: Arc<DynamicImage>) -> Self {
+        Layer {
+            name,
+            instance: 0,
+            image,
+            position: None,
+            size: None,
+            effects: Vec::new(),
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Take over the state of every parameter that has the same source
+    /// as in `prev`, so unchanged animations keep going.
+    pub fn take_state_from(&mut self, prev: &mut Layer) {
+        let mut prev_params: HashMap<ParamKey, &mut Box<dyn Parameter>> =
+            params_mut(&mut prev.position, &mut prev.size, &mut prev.effects)
+                .into_iter()
+                .collect();
+
+        for (key, param) in params_mut(&mut self.position, &mut self.size, &mut self.effects) {
+            let unchanged = matches!(
+                (self.sources.get(&key), prev.sources.get(&key)),
+                (Some(new), Some(old)) if new == old
+            );
+            if unchanged {
+                if let Some(prev_param) = prev_params.remove(&key) {
+                    std::mem::swap(param, prev_param);
+                }
+            }
+        }
+    }
+
+    /// Advance all parameters by one step and produce the processed image.
+    pub fn step(&mut self, ctx: &mut Context) -> Stamp {
+        let mut image = (*self.image).clone();
+
+        let mut x = 0.0_f32;
+        let mut y = 0.0_f32;
+        let mut w = 50.0_f32;
+        let mut h = 50.0_f32;
+
+        if let Some(ImgParams::Position(xp, yp)) = &mut self.position {
+            x = xp.get_next(ctx);
+            y = yp.get_next(ctx);
+        }
+
+        if let Some(ImgParams::Size(wp, hp)) = &mut self.size {
+            w = wp.get_next(ctx);
+            h = hp.get_next(ctx);
+        }
+
+        for param in self.effects.iter_mut() {
+            match param {
+                ImgParams::Blur(f) => {
+                    image = image.blur(f.get_next(ctx));
+                }
+                ImgParams::Brighten(f) => {
+                    image = image.brighten(f.get_next(ctx) as i32);
+                }
+                ImgParams::Contrast(f) => {
+                    image = image.adjust_contrast(f.get_next(ctx));
+                }
+                ImgParams::HueRot(f) => {
+                    image = image.huerotate(f.get_next(ctx) as i32);
+                }
+                ImgParams::Crop(x, y, w, h) => {
+                    image = image.crop(
+                        ((x.get_next(ctx) + 0.01) * image.width() as f32) as u32,
+                        ((y.get_next(ctx) + 0.01) * image.height() as f32) as u32,
+                        ((w.get_next(ctx) + 0.01) * image.width() as f32) as u32,
+                        ((h.get_next(ctx) + 0.01) * image.height() as f32) as u32,
+                    )
+                }
+                ImgParams::Opacity(o) => {
+                    let val = o.get_next(ctx);
+                    let mut ibuf = image.clone().into_rgba8();
+
+                    for p in ibuf.pixels_mut() {
+                        *p = p.map_with_alpha(|x| x, |a| (a as f32 * val) as u8);
+                    }
+                    image = DynamicImage::ImageRgba8(ibuf);
+                }
+                ImgParams::Brownian(xw, yw) => {
+                    x += xw.get_next(ctx);
+                    y += yw.get_next(ctx);
+                }
+                ImgParams::Scatter(f) => {
+                    let val = f.get_next(ctx);
+                    let scatter_x: f32 = ctx.rng.gen::<f32>() * val;
+                    let scatter_y: f32 = ctx.rng.gen::<f32>() * val;
+                    x *= scatter_x;
+                    y *= scatter_y;
+                }
+                _ => {}
+            }
+        }
+
+        // to be save ...
+        if w == 0.0 {
+            w = 1.0;
+        }
+        if h == 0.0 {
+            h = 1.0;
+        }
+
+        Stamp { image, x, y, w, h }
+    }
+}
+
+/// Put each group of `new` layers with the same name in place of the
+/// existing layers with that name, or on top if there are none. All
+/// instances of a repeated line are replaced together, so there are no
+/// leftovers when the count goes down.
+pub fn merge_layers(layers: &mut Vec<Layer>, new: Vec<Layer>) {
+    let mut new = new.into_iter().peekable();
+
+    while let Some(first) = new.next() {
+        let mut group = vec![first];
+        while let Some(layer) = new.next_if(|layer| layer.name == group[0].name) {
+            group.push(layer);
+        }
+
+        let name = group[0].name.clone();
+        match layers.iter().position(|layer| layer.name == name) {
+            Some(at) => {
+                layers.retain(|layer| layer.name != name);
+                layers.splice(at..at, group);
+            }
+            None => layers.extend(group),
+        }
+    }
+}
+
+fn params_mut<'a>(
+    position: &'a mut Option<ImgParams>,
+    size: &'a mut Option<ImgParams>,
+    effects: &'a mut [ImgParams],
+) -> Vec<(ParamKey, &'a mut Box<dyn Parameter>)> {
+    let mut params = Vec::new();
+    let mut occurrences = HashMap::<&'static str, usize>::new();
+
+    for img_param in position.iter_mut().chain(size.iter_mut()).chain(effects) {
+        let command = img_param.name();
+        let occurrence = occurrences.entry(command).or_insert(0);
+        for (arg, param) in img_param.params_mut().into_iter().enumerate() {
+            params.push((
+                ParamKey {
+                    command,
+                    occurrence: *occurrence,
+                    arg,
+                },
+                param,
+            ));
+        }
+        *occurrence += 1;
+    }
+
+    params
+}
@@ -0,0 +1,23 @@
+//! Paint images with images, controlled with text.
+//!
+//! Code is parsed into an AST, interpreted into layers of parameterized
+//! image operations and rendered frame by frame, with or without a window.
+
+pub mod ast;
+pub mod clock;
+pub mod commands;
+pub mod compositor;
+pub mod diagnostic;
+pub mod easing;
+pub mod image_cache;
+pub mod img_params;
+pub mod interpreter;
+pub mod layer;
+pub mod line_parser;
+pub mod parameter;
+pub mod render;
+pub mod scene;
+
+pub use diagnostic::Diagnostic;
+pub use layer::{Layer, Stamp};
+pub use scene::Scene;
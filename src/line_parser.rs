@@ -1,86 +1,462 @@
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_while},
+    bytes::complete::{tag, take_while1},
+    character::complete::{char, digit1, one_of, space0, space1},
     character::is_alphanumeric,
-    combinator::{map, map_res, recognize},
-    error::VerboseError,
-    multi::{separated_list0, separated_list1},
-    number::complete::float,
-    sequence::{delimited, separated_pair},
-    IResult,
+    combinator::{cut, map, map_res, opt, value},
+    error::{context, VerboseError, VerboseErrorKind},
+    multi::many0,
+    number::complete::recognize_float,
+    sequence::{delimited, pair, preceded, terminated, tuple},
+    Err, IResult, Offset,
 };
 
-pub enum ParserResult {
-    String(String),
-    Scalar(f32),
-    Bounce(Vec<f32>),
-    Ramp(Vec<f32>),
-    Choose(Vec<f32>),
-    Cycle(Vec<f32>),
+use std::ops::Range;
+
+use crate::ast::*;
+use crate::diagnostic::{column_of, word_at, Diagnostic};
+
+type ParseResult<'a, O> = IResult<&'a str, O, VerboseError<&'a str>>;
+
+/// The line being parsed, needed to compute spans.
+#[derive(Clone, Copy)]
+struct Line<'a> {
+    no: usize,
+    text: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn span(&self, from: &'a str, to: &'a str) -> Span {
+        let start = self.text.offset(from);
+        Span {
+            line: self.no,
+            column: column_of(self.text, start),
+            start,
+            end: self.text.offset(to),
+        }
+    }
+}
+
+/// Wrap the result of `parser` in a `Spanned` node.
+fn spanned<'a, O>(
+    line: Line<'a>,
+    mut parser: impl FnMut(&'a str) -> ParseResult<'a, O>,
+) -> impl FnMut(&'a str) -> ParseResult<'a, Spanned<O>> {
+    move |i| {
+        let (rest, node) = parser(i)?;
+        Ok((
+            rest,
+            Spanned {
+                node,
+                span: line.span(i, rest),
+            },
+        ))
+    }
+}
+
+fn parse_float(i: &str) -> ParseResult<'_, f32> {
+    map_res(recognize_float, |digit_str: &str| digit_str.parse::<f32>())(i)
+}
+
+/// valid chars for a function name
+fn valid_char(chr: char) -> bool {
+    chr == '_' || chr == '.' || chr == '-' || is_alphanumeric(chr as u8)
 }
 
-fn parse_param(i: &str) -> IResult<&str, ParserResult, VerboseError<&str>> {
+fn parse_word(i: &str) -> ParseResult<'_, String> {
+    map(take_while1(valid_char), |desc_str: &str| {
+        desc_str.to_string()
+    })(i)
+}
+
+/// `index` or `n`
+fn parse_instance_value(i: &str) -> ParseResult<'_, InstanceValue> {
+    map_res(parse_word, |word| match word.as_str() {
+        "index" => Ok(InstanceValue::Index),
+        "n" => Ok(InstanceValue::Count),
+        _ => Err(()),
+    })(i)
+}
+
+/// `4s`, `500ms` or `2b`
+fn parse_duration(i: &str) -> ParseResult<'_, ParamExpr> {
     alt((
-        map(parse_float, ParserResult::Scalar),
-        delimited(
-            tag("["),
-            alt((
-                map(parse_float, ParserResult::Scalar),
-                map(
-                    separated_pair(
-                        tag("ramp"),
-                        tag(" "),
-                        separated_list0(tag(" "), parse_float),
-                    ),
-                    |v| ParserResult::Ramp(v.1),
-                ),
-                map(
-                    separated_pair(
-                        tag("bounce"),
-                        tag(" "),
-                        separated_list0(tag(" "), parse_float),
-                    ),
-                    |v| ParserResult::Bounce(v.1),
-                ),
-                map(
-                    separated_pair(
-                        tag("choose"),
-                        tag(" "),
-                        separated_list0(tag(" "), parse_float),
-                    ),
-                    |v| ParserResult::Choose(v.1),
-                ),
-                map(
-                    separated_pair(
-                        tag("cycle"),
-                        tag(" "),
-                        separated_list0(tag(" "), parse_float),
+        map(terminated(parse_float, tag("ms")), |ms| {
+            ParamExpr::Duration(ms / 1000.0, TimeUnit::Seconds)
+        }),
+        map(terminated(parse_float, tag("s")), |s| {
+            ParamExpr::Duration(s, TimeUnit::Seconds)
+        }),
+        map(terminated(parse_float, tag("b")), |b| {
+            ParamExpr::Duration(b, TimeUnit::Beats)
+        }),
+    ))(i)
+}
+
+/// A number, a duration or an instance value.
+fn parse_number(i: &str) -> ParseResult<'_, ParamExpr> {
+    alt((
+        parse_duration,
+        map(parse_float, ParamExpr::Scalar),
+        map(parse_instance_value, ParamExpr::Instance),
+    ))(i)
+}
+
+/// `quant 50`, following a `|`
+fn parse_modifier<'a>(line: Line<'a>) -> impl FnMut(&'a str) -> ParseResult<'a, Modifier> {
+    map(
+        pair(
+            spanned(line, parse_word),
+            many0(preceded(space1, move |i| parse_factor(line, i))),
+        ),
+        |(name, args)| Modifier { name, args },
+    )
+}
+
+/// `ramp 0 1 100`, `cycle 0 1 2 /4` or `choose 0 100 | lag 1s`, without
+/// the brackets
+fn parse_generator<'a>(line: Line<'a>) -> impl FnMut(&'a str) -> ParseResult<'a, Generator> {
+    map(
+        tuple((
+            spanned(line, parse_word),
+            many0(preceded(
+                space1,
+                alt((
+                    move |i| parse_factor(line, i),
+                    spanned(line, map(parse_word, ParamExpr::Keyword)),
+                )),
+            )),
+            opt(preceded(
+                tuple((space1, char('/'), space0)),
+                cut(context("steps per beat", spanned(line, parse_float))),
+            )),
+            many0(preceded(
+                tuple((space0, char('|'), space0)),
+                cut(context("modifier", parse_modifier(line))),
+            )),
+        )),
+        |(name, args, sync, modifiers)| Generator {
+            name,
+            args,
+            sync,
+            modifiers,
+        },
+    )
+}
+
+/// Keep the inner node, but with the span of the whole group.
+fn grouped<'a>(
+    line: Line<'a>,
+    parser: impl FnMut(&'a str) -> ParseResult<'a, Spanned<ParamExpr>>,
+) -> impl FnMut(&'a str) -> ParseResult<'a, Spanned<ParamExpr>> {
+    map(spanned(line, parser), |group| Spanned {
+        node: group.node.node,
+        span: group.span,
+    })
+}
+
+/// A number, an instance value, `$<variable>`, `-<factor>`, `(<expr>)`
+/// or `[<expr or generator>]`.
+fn parse_factor<'a>(line: Line<'a>, i: &'a str) -> ParseResult<'a, Spanned<ParamExpr>> {
+    alt((
+        spanned(line, parse_number),
+        spanned(
+            line,
+            map(
+                preceded(char('$'), cut(context("variable name", parse_word))),
+                ParamExpr::Var,
+            ),
+        ),
+        spanned(
+            line,
+            map(
+                preceded(char('-'), move |i| parse_factor(line, i)),
+                |inner| ParamExpr::Neg(Box::new(inner)),
+            ),
+        ),
+        grouped(
+            line,
+            preceded(
+                terminated(char('('), space0),
+                cut(terminated(
+                    context("expression", move |i| parse_expr(line, i)),
+                    context("operator or `)`", preceded(space0, tag(")"))),
+                )),
+            ),
+        ),
+        grouped(
+            line,
+            preceded(
+                tag("["),
+                cut(terminated(
+                    preceded(
+                        space0,
+                        context(
+                            "number or generator",
+                            alt((
+                                move |i| parse_expr(line, i),
+                                spanned(line, map(parse_generator(line), ParamExpr::Generator)),
+                            )),
+                        ),
                     ),
-                    |v| ParserResult::Cycle(v.1),
+                    context("number or `]`", preceded(space0, tag("]"))),
+                )),
+            ),
+        ),
+    ))(i)
+}
+
+/// One of `ops`, either with spaces on both sides or none, so that
+/// `pos 0 -2` is still two parameters.
+fn parse_operator<'a>(ops: &'static str) -> impl FnMut(&'a str) -> ParseResult<'a, BinOp> {
+    map(
+        alt((delimited(space1, one_of(ops), space1), one_of(ops))),
+        |op| match op {
+            '+' => BinOp::Add,
+            '-' => BinOp::Sub,
+            '*' => BinOp::Mul,
+            _ => BinOp::Div,
+        },
+    )
+}
+
+/// Left-associative chain of `operand`s joined by one of `ops`.
+fn parse_chain<'a>(
+    line: Line<'a>,
+    i: &'a str,
+    ops: &'static str,
+    operand: fn(Line<'a>, &'a str) -> ParseResult<'a, Spanned<ParamExpr>>,
+) -> ParseResult<'a, Spanned<ParamExpr>> {
+    let (mut rest, mut left) = operand(line, i)?;
+
+    loop {
+        let after_op = match parse_operator(ops)(rest) {
+            Ok((after_op, op)) => {
+                let (after, right) =
+                    cut(context("number or generator", |i| operand(line, i)))(after_op)?;
+                let span = Span {
+                    line: line.no,
+                    column: left.span.column,
+                    start: left.span.start,
+                    end: right.span.end,
+                };
+                left = Spanned {
+                    node: ParamExpr::Binary(op, Box::new(left), Box::new(right)),
+                    span,
+                };
+                after
+            }
+            Err(Err::Error(_)) => return Ok((rest, left)),
+            Err(e) => return Err(e),
+        };
+        rest = after_op;
+    }
+}
+
+fn parse_term<'a>(line: Line<'a>, i: &'a str) -> ParseResult<'a, Spanned<ParamExpr>> {
+    parse_chain(line, i, "*/", parse_factor)
+}
+
+/// `[bounce -200 200 300] + [choose -20 0 20] * 2`
+fn parse_expr<'a>(line: Line<'a>, i: &'a str) -> ParseResult<'a, Spanned<ParamExpr>> {
+    parse_chain(line, i, "+-", parse_term)
+}
+
+/// `pos 0 [bounce 0 100]`
+fn parse_command<'a>(line: Line<'a>) -> impl FnMut(&'a str) -> ParseResult<'a, Command> {
+    map(
+        pair(
+            spanned(line, parse_word),
+            many0(preceded(space1, move |i| parse_expr(line, i))),
+        ),
+        |(name, args)| Command { name, args },
+    )
+}
+
+/// `repeat 20` or `instances 20`
+fn parse_repeat(i: &str) -> ParseResult<'_, usize> {
+    preceded(
+        terminated(alt((tag("repeat"), tag("instances"))), space1),
+        cut(context(
+            "number of instances",
+            map_res(digit1, |digit_str: &str| digit_str.parse::<usize>()),
+        )),
+    )(i)
+}
+
+/// `img forest.jpg pos 0 0 size 100 100` or `img forest.jpg as tree1 repeat 4 pos 0 0`
+fn parse_image<'a>(line: Line<'a>) -> impl FnMut(&'a str) -> ParseResult<'a, ImageStatement> {
+    map(
+        preceded(
+            terminated(tag("img"), space1),
+            cut(tuple((
+                context("image file name", spanned(line, parse_word)),
+                opt(preceded(
+                    tuple((space1, tag("as"), space1)),
+                    cut(context("alias", spanned(line, parse_word))),
+                )),
+                opt(preceded(space1, spanned(line, parse_repeat))),
+                many0(preceded(space1, spanned(line, parse_command(line)))),
+            ))),
+        ),
+        |(file, alias, repeat, commands)| ImageStatement {
+            file,
+            alias,
+            repeat,
+            commands,
+        },
+    )
+}
+
+/// `let wob = [bounce 0 1 200]`
+fn parse_let<'a>(line: Line<'a>) -> impl FnMut(&'a str) -> ParseResult<'a, LetStatement> {
+    map(
+        preceded(
+            terminated(tag("let"), space1),
+            cut(pair(
+                context("variable name", spanned(line, parse_word)),
+                preceded(
+                    context("`=`", delimited(space0, tag("="), space0)),
+                    context("number or generator", move |i| parse_expr(line, i)),
                 ),
             )),
-            tag("]"),
         ),
-    ))(i)
+        |(name, value)| LetStatement { name, value },
+    )
 }
 
-fn parse_float(i: &str) -> IResult<&str, f32, VerboseError<&str>> {
-    map_res(recognize(float), |digit_str: &str| digit_str.parse::<f32>())(i)
+/// `bpm 128`
+fn parse_bpm(i: &str) -> ParseResult<'_, f32> {
+    preceded(
+        terminated(tag("bpm"), space1),
+        cut(context("tempo", parse_float)),
+    )(i)
 }
 
-/// valid chars for a function name
-fn valid_char(chr: char) -> bool {
-    chr == '_' || chr == '.' || chr == '-' || is_alphanumeric(chr as u8)
+/// `seed 42`
+fn parse_seed(i: &str) -> ParseResult<'_, u64> {
+    preceded(
+        terminated(tag("seed"), space1),
+        cut(context(
+            "whole number",
+            map_res(digit1, |digit_str: &str| digit_str.parse::<u64>()),
+        )),
+    )(i)
 }
 
-fn parse_string(i: &str) -> IResult<&str, ParserResult, VerboseError<&str>> {
-    map(take_while(valid_char), |desc_str: &str| {
-        ParserResult::String(desc_str.to_string())
-    })(i)
+fn parse_statement<'a>(line: Line<'a>) -> impl FnMut(&'a str) -> ParseResult<'a, Statement> {
+    context(
+        "statement (img, let, seed, bpm, reload)",
+        alt((
+            map(parse_image(line), Statement::Image),
+            map(parse_let(line), Statement::Let),
+            map(parse_seed, Statement::Seed),
+            map(parse_bpm, Statement::Bpm),
+            value(Statement::Reload, tag("reload")),
+        )),
+    )
+}
+
+/// Parse a single line into a statement, turning anything the parser can't
+/// make sense of into a diagnostic. `line_no` is 1-based.
+pub fn parse_line(line_no: usize, text: &str) -> Result<Spanned<Statement>, Diagnostic> {
+    let line = Line { no: line_no, text };
+
+    match delimited(space0, spanned(line, parse_statement(line)), space0)(text) {
+        Ok(("", statement)) => Ok(statement),
+        Ok((rest, _)) => {
+            let rest = rest.trim_start();
+            Err(
+                Diagnostic::new(line_no, column_of(text, text.offset(rest)), "syntax error")
+                    .expected("parameter or command")
+                    .found(word_at(rest)),
+            )
+        }
+        Err(Err::Error(e)) | Err(Err::Failure(e)) => Err(to_diagnostic(line, e)),
+        Err(Err::Incomplete(_)) => Err(Diagnostic::new(line_no, 1, "incomplete line")),
+    }
+}
+
+/// Parse a whole script. Empty lines and lines starting with `#` are skipped,
+/// lines that fail to parse are reported and left out.
+pub fn parse_script(source: &str) -> (Script, Vec<Diagnostic>) {
+    parse_lines(source, 0..usize::MAX)
+}
+
+/// Like `parse_script`, but only looking at the 0-based line indices in
+/// `lines`. Line numbers still count from the start of `source`.
+pub fn parse_lines(source: &str, lines: Range<usize>) -> (Script, Vec<Diagnostic>) {
+    let mut script = Script::default();
+    let mut diagnostics = Vec::new();
+
+    for (line_idx, text) in source.split('\n').enumerate() {
+        if !lines.contains(&line_idx) {
+            continue;
+        }
+
+        if text.trim().is_empty() || matches!(text.trim_start().chars().next(), Some('#')) {
+            continue;
+        }
+
+        match parse_line(line_idx + 1, text) {
+            Ok(statement) => script.statements.push(statement),
+            Err(diag) => diagnostics.push(diag),
+        }
+    }
+
+    (script, diagnostics)
 }
 
-pub fn parse_line(i: &str) -> IResult<&str, Vec<ParserResult>, VerboseError<&str>> {
-    separated_list1(tag(" "), alt((parse_param, parse_string)))(i)
+/// The block of consecutive non-empty lines around the char at `cursor`,
+/// as a range of 0-based line indices. Empty if the cursor is on an empty line.
+pub fn block_at(source: &str, cursor: usize) -> Range<usize> {
+    let lines: Vec<&str> = source.split('\n').collect();
+    let current = source.chars().take(cursor).filter(|c| *c == '\n').count();
+    let current = current.min(lines.len() - 1);
+
+    if lines[current].trim().is_empty() {
+        return current..current;
+    }
+
+    let mut start = current;
+    while start > 0 && !lines[start - 1].trim().is_empty() {
+        start -= 1;
+    }
+    let mut end = current + 1;
+    while end < lines.len() && !lines[end].trim().is_empty() {
+        end += 1;
+    }
+
+    start..end
+}
+
+fn to_diagnostic(line: Line, e: VerboseError<&str>) -> Diagnostic {
+    let rest = e.errors.first().map(|(rest, _)| *rest).unwrap_or(line.text);
+    let rest = rest.trim_start();
+
+    // contexts say more than the single chars nom was looking for
+    let expected = e
+        .errors
+        .iter()
+        .find_map(|(_, kind)| match kind {
+            VerboseErrorKind::Context(ctx) => Some(ctx.to_string()),
+            _ => None,
+        })
+        .or_else(|| {
+            e.errors.iter().find_map(|(_, kind)| match kind {
+                VerboseErrorKind::Char(c) => Some(format!("`{}`", c)),
+                _ => None,
+            })
+        })
+        .unwrap_or_else(|| "parameter or command".to_string());
+
+    Diagnostic::new(
+        line.no,
+        column_of(line.text, line.text.offset(rest)),
+        "syntax error",
+    )
+    .expected(expected)
+    .found(word_at(rest))
 }
 
 #[cfg(test)]
@@ -90,18 +466,285 @@ mod tests {
 
     #[test]
     fn test_line_parser() {
-        let result = parse_line(
-            "img forest.jpg pos 0.2 [0.2] [bounce 0.2 0.3] [ramp 0.2 0.3] [choose 0.2 0.3 400]",
+        let statement = parse_line(
+            1,
+            "img forest.jpg pos 0.2 [0.2] size [bounce 0.2 0.3] [ramp 0.2 0.3] crop [choose 0.2 0.3 400]",
+        )
+        .unwrap();
+        let Statement::Image(img) = statement.node else {
+            panic!("expected img statement");
+        };
+        assert_eq!(img.file.node, "forest.jpg");
+        assert_eq!(img.file.span.column, 5);
+        assert_eq!(img.alias, None);
+        assert_eq!(img.commands.len(), 3);
+
+        let pos = &img.commands[0].node;
+        assert_eq!(pos.name.node, "pos");
+        assert!(matches!(pos.args[0].node, ParamExpr::Scalar(_)));
+        assert!(matches!(pos.args[1].node, ParamExpr::Scalar(_)));
+        assert_eq!(pos.args[1].span.column, 24);
+
+        let size = &img.commands[1].node;
+        assert!(matches!(&size.args[0].node, ParamExpr::Generator(g) if g.name.node == "bounce"));
+        assert!(matches!(&size.args[1].node, ParamExpr::Generator(g) if g.name.node == "ramp"));
+
+        let crop = &img.commands[2].node;
+        match &crop.args[0].node {
+            ParamExpr::Generator(g) => {
+                assert_eq!(g.name.node, "choose");
+                assert_eq!(g.args.len(), 3);
+                assert_eq!(g.args[2].node, ParamExpr::Scalar(400.0));
+            }
+            _ => panic!("expected generator"),
+        }
+    }
+
+    #[test]
+    fn test_alias_parser() {
+        let statement = parse_line(1, "img forest.jpg as tree1 pos 0 0").unwrap();
+        let Statement::Image(img) = statement.node else {
+            panic!("expected img statement");
+        };
+        assert_eq!(img.file.node, "forest.jpg");
+        assert_eq!(img.alias.as_ref().unwrap().span.column, 19);
+        assert_eq!(img.layer_name(), "tree1");
+        assert_eq!(img.commands.len(), 1);
+
+        let diag = parse_line(1, "img forest.jpg as [ramp]").err().unwrap();
+        assert_eq!(diag.column, 19);
+        assert_eq!(diag.expected.as_deref(), Some("alias"));
+    }
+
+    #[test]
+    fn test_repeat_parser() {
+        let statement =
+            parse_line(1, "img leaf.png as leaf repeat 20 pos index [ramp 0 n 10]").unwrap();
+        let Statement::Image(img) = statement.node else {
+            panic!("expected img statement");
+        };
+        assert_eq!(img.repeat.as_ref().unwrap().node, 20);
+        let pos = &img.commands[0].node;
+        assert_eq!(pos.args[0].node, ParamExpr::Instance(InstanceValue::Index));
+        assert_eq!(pos.args[1].node.to_string(), "[ramp 0 n 10]");
+
+        let statement = parse_line(1, "img leaf.png instances 3").unwrap();
+        let Statement::Image(img) = statement.node else {
+            panic!("expected img statement");
+        };
+        assert_eq!(img.repeat.unwrap().node, 3);
+
+        let diag = parse_line(1, "img leaf.png repeat x").err().unwrap();
+        assert_eq!(diag.column, 21);
+        assert_eq!(diag.expected.as_deref(), Some("number of instances"));
+    }
+
+    #[test]
+    fn test_expression_parser() {
+        let statement = parse_line(
+            1,
+            "img a.jpg pos [bounce -200 200 300] + [choose -20 0 20] * 2 -(1 - (2 - 3))",
+        )
+        .unwrap();
+        let Statement::Image(img) = statement.node else {
+            panic!("expected img statement");
+        };
+        let pos = &img.commands[0].node;
+        assert_eq!(pos.args.len(), 2);
+        assert_eq!(
+            pos.args[0].node.to_string(),
+            "[bounce -200 200 300] + [choose -20 0 20] * 2"
+        );
+        assert!(matches!(
+            pos.args[0].node,
+            ParamExpr::Binary(BinOp::Add, ..)
+        ));
+        assert_eq!(pos.args[0].span.column, 15);
+        assert_eq!(pos.args[1].node.to_string(), "-(1 - (2 - 3))");
+        assert_eq!(pos.args[1].span.column, 61);
+
+        // tight operators, unary minus on its own is a new parameter
+        let statement = parse_line(1, "img a.jpg pos [1*2]-index -2").unwrap();
+        let Statement::Image(img) = statement.node else {
+            panic!("expected img statement");
+        };
+        let pos = &img.commands[0].node;
+        assert_eq!(pos.args[0].node.to_string(), "1 * 2 - index");
+        assert_eq!(pos.args[1].node, ParamExpr::Scalar(-2.0));
+
+        let diag = parse_line(1, "img a.jpg pos (1 + 2 0").err().unwrap();
+        assert_eq!(diag.column, 22);
+        assert_eq!(diag.expected.as_deref(), Some("operator or `)`"));
+        let diag = parse_line(1, "img a.jpg pos 1 * size 2").err().unwrap();
+        assert_eq!(diag.column, 19);
+        assert_eq!(diag.expected.as_deref(), Some("number or generator"));
+    }
+
+    #[test]
+    fn test_let_parser() {
+        let statement = parse_line(1, "let wob = [bounce 0 1 200] * 2").unwrap();
+        let Statement::Let(def) = statement.node else {
+            panic!("expected let statement");
+        };
+        assert_eq!(def.name.node, "wob");
+        assert_eq!(def.value.node.to_string(), "[bounce 0 1 200] * 2");
+
+        let statement = parse_line(1, "img a.jpg opacity $wob").unwrap();
+        let Statement::Image(img) = statement.node else {
+            panic!("expected img statement");
+        };
+        assert_eq!(
+            img.commands[0].node.args[0].node,
+            ParamExpr::Var("wob".into())
+        );
+
+        let diag = parse_line(1, "let wob [bounce 0 1 200]").err().unwrap();
+        assert_eq!(diag.column, 9);
+        assert_eq!(diag.expected.as_deref(), Some("`=`"));
+    }
+
+    #[test]
+    fn test_duration_parser() {
+        let statement = parse_line(
+            1,
+            "img a.jpg pos [ramp 0 1 4s] [bounce 0 1 500ms] size [ramp 0 1 2b] 1",
+        )
+        .unwrap();
+        let Statement::Image(img) = statement.node else {
+            panic!("expected img statement");
+        };
+        let durations: Vec<String> = img
+            .commands
+            .iter()
+            .flat_map(|command| command.node.args.iter())
+            .map(|arg| arg.node.to_string())
+            .collect();
+        assert_eq!(
+            durations,
+            ["[ramp 0 1 4s]", "[bounce 0 1 0.5s]", "[ramp 0 1 2b]", "1"]
+        );
+    }
+
+    #[test]
+    fn test_keyword_parser() {
+        let statement = parse_line(
+            1,
+            "img a.jpg opacity [ease out-bounce [ramp 0 1 120 ease-in-out-cubic]]",
         )
         .unwrap();
-        println!("{}", result.0);
-        assert!(matches!(result.1[0], ParserResult::String(_)));
-        assert!(matches!(result.1[1], ParserResult::String(_)));
-        assert!(matches!(result.1[2], ParserResult::String(_)));
-        assert!(matches!(result.1[3], ParserResult::Scalar(_)));
-        assert!(matches!(result.1[4], ParserResult::Scalar(_)));
-        assert!(matches!(result.1[5], ParserResult::Bounce(_)));
-        assert!(matches!(result.1[6], ParserResult::Ramp(_)));
-        assert!(matches!(result.1[7], ParserResult::Choose(_)));
+        let Statement::Image(img) = statement.node else {
+            panic!("expected img statement");
+        };
+        let ParamExpr::Generator(ease) = &img.commands[0].node.args[0].node else {
+            panic!("expected generator");
+        };
+        assert_eq!(ease.args[0].node, ParamExpr::Keyword("out-bounce".into()));
+        let ParamExpr::Generator(ramp) = &ease.args[1].node else {
+            panic!("expected generator");
+        };
+        assert_eq!(ramp.args[3].node.to_string(), "ease-in-out-cubic");
+        assert_eq!(ramp.args[3].span.column, 50);
+    }
+
+    #[test]
+    fn test_line_parser_errors() {
+        let diag = parse_line(1, "img forest.jpg pos [ramp 0 %] 0")
+            .err()
+            .unwrap();
+        assert_eq!(diag.column, 28);
+        assert_eq!(diag.expected.as_deref(), Some("number or `]`"));
+        assert_eq!(diag.found.as_deref(), Some("%"));
+
+        let diag = parse_line(4, "img forest.jpg pos 0 %").err().unwrap();
+        assert_eq!(diag.line, 4);
+        assert_eq!(diag.column, 22);
+        assert_eq!(diag.found.as_deref(), Some("%"));
+
+        let diag = parse_line(1, "pos 0 0").err().unwrap();
+        assert_eq!(diag.column, 1);
+        assert_eq!(
+            diag.expected.as_deref(),
+            Some("statement (img, let, seed, bpm, reload)")
+        );
+
+        let diag = parse_line(1, "seed -1").err().unwrap();
+        assert_eq!(diag.column, 6);
+        assert_eq!(diag.expected.as_deref(), Some("whole number"));
+    }
+
+    #[test]
+    fn test_block_at() {
+        let source = "seed 1\nimg a.jpg\n\nimg b.jpg\n# comment\nimg c.jpg\n";
+        assert_eq!(block_at(source, 0), 0..2);
+        assert_eq!(block_at(source, 9), 0..2);
+        assert_eq!(block_at(source, 17), 2..2);
+        assert_eq!(block_at(source, 20), 3..6);
+        assert_eq!(block_at(source, source.chars().count()), 6..6);
+
+        let (script, _) = parse_lines(source, 3..6);
+        assert_eq!(script.statements.len(), 2);
+        assert_eq!(script.statements[0].span.line, 4);
+    }
+
+    #[test]
+    fn test_seed_parser() {
+        let statement = parse_line(1, "seed 1234").unwrap();
+        assert_eq!(statement.node, Statement::Seed(1234));
+        let statement = parse_line(1, " reload ").unwrap();
+        assert_eq!(statement.node, Statement::Reload);
+        let statement = parse_line(1, "bpm 128").unwrap();
+        assert_eq!(statement.node, Statement::Bpm(128.0));
+    }
+
+    #[test]
+    fn test_modifier_parser() {
+        let statement = parse_line(
+            1,
+            "img a.jpg pos [choose 0 100 200 /2 | lag 100ms|quant 50] 0",
+        )
+        .unwrap();
+        let Statement::Image(img) = statement.node else {
+            panic!("expected img statement");
+        };
+        let arg = &img.commands[0].node.args[0].node;
+        let ParamExpr::Generator(gen) = arg else {
+            panic!("expected generator");
+        };
+        assert_eq!(gen.args.len(), 3);
+        assert_eq!(gen.modifiers.len(), 2);
+        assert_eq!(gen.modifiers[1].name.node, "quant");
+        assert_eq!(gen.modifiers[1].name.span.column, 48);
+        assert_eq!(
+            arg.to_string(),
+            "[choose 0 100 200 /2 | lag 0.1s | quant 50]"
+        );
+
+        let diag = parse_line(1, "img a.jpg pos [choose 0 100 | ] 0")
+            .err()
+            .unwrap();
+        assert_eq!(diag.column, 31);
+        assert_eq!(diag.expected.as_deref(), Some("modifier"));
+    }
+
+    #[test]
+    fn test_sync_parser() {
+        let statement = parse_line(1, "img a.jpg pos [cycle 0 1 2 /4] [choose 1 2 / 2]").unwrap();
+        let Statement::Image(img) = statement.node else {
+            panic!("expected img statement");
+        };
+        let pos = &img.commands[0].node;
+        let ParamExpr::Generator(gen) = &pos.args[0].node else {
+            panic!("expected generator");
+        };
+        assert_eq!(gen.args.len(), 3);
+        assert_eq!(gen.sync.as_ref().unwrap().node, 4.0);
+        assert_eq!(pos.args[0].node.to_string(), "[cycle 0 1 2 /4]");
+        assert_eq!(pos.args[1].node.to_string(), "[choose 1 2 /2]");
+
+        let diag = parse_line(1, "img a.jpg pos [cycle 0 1 /x] 0")
+            .err()
+            .unwrap();
+        assert_eq!(diag.column, 27);
+        assert_eq!(diag.expected.as_deref(), Some("steps per beat"));
     }
 }
@@ -1,7 +1,172 @@
+use rand::rngs::StdRng;
+
 use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::ast::BinOp;
+use crate::clock::Clock;
+use crate::easing::Easing;
+
+/// Shared state that parameters are evaluated against.
+pub struct Context {
+    pub rng: StdRng,
+    /// seed of the field that all `noise2` generators sample
+    pub noise_seed: u64,
+    pub clock: Clock,
+    variables: HashMap<String, Variable>,
+    /// names of the keys held down right now, like `a` or `space`
+    keys: HashSet<String>,
+}
+
+/// A parameter defined with `let`. However often it's read, it only
+/// advances once per frame.
+pub struct Variable {
+    param: Box<dyn Parameter>,
+    /// normalized source, to tell if the definition changed
+    source: String,
+    last: Option<(u64, f32)>,
+}
+
+impl Variable {
+    pub fn new(param: Box<dyn Parameter>, source: String) -> Self {
+        Variable {
+            param,
+            source,
+            last: None,
+        }
+    }
+
+    /// The value for the current frame, computing it on the first read.
+    fn read(&mut self, ctx: &mut Context) -> f32 {
+        match self.last {
+            Some((frame, value)) if frame == ctx.clock.frame() => value,
+            _ => {
+                let value = self.param.get_next(ctx);
+                self.last = Some((ctx.clock.frame(), value));
+                value
+            }
+        }
+    }
+}
+
+impl Context {
+    pub fn new(seed: u64) -> Self {
+        Context {
+            rng: StdRng::seed_from_u64(seed),
+            noise_seed: seed,
+            clock: Clock::new(),
+            variables: HashMap::new(),
+            keys: HashSet::new(),
+        }
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.noise_seed = seed;
+    }
+
+    /// Define `name`, unless it's already defined the same way, in which
+    /// case it keeps its state.
+    pub fn define(&mut self, name: String, var: Variable) {
+        match self.variables.get(&name) {
+            Some(existing) if existing.source == var.source => {}
+            _ => {
+                self.variables.insert(name, var);
+            }
+        }
+    }
+
+    /// Forget all variables for which `keep` is false.
+    pub fn retain_variables(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        self.variables.retain(|name, _| keep(name));
+    }
+
+    pub fn variable_names(&self) -> impl Iterator<Item = &str> {
+        self.variables.keys().map(|name| name.as_str())
+    }
+
+    pub fn press_key(&mut self, key: &str) {
+        self.keys.insert(key.to_string());
+    }
+
+    pub fn release_key(&mut self, key: &str) {
+        self.keys.remove(key);
+    }
+
+    pub fn key_down(&self, key: &str) -> bool {
+        self.keys.contains(key)
+    }
+
+    /// The current value of `name`, 0 if it isn't defined (or is being
+    /// read while it's computing its own value).
+    pub fn read_variable(&mut self, name: &str) -> f32 {
+        let Some(mut var) = self.variables.remove(name) else {
+            return 0.0;
+        };
+
+        let value = var.read(self);
+        self.variables.insert(name.to_string(), var);
+        value
+    }
+}
 
 pub trait Parameter {
-    fn get_next(&mut self) -> f32;
+    fn get_next(&mut self, ctx: &mut Context) -> f32;
+}
+
+/// How long one cycle of a periodic generator takes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    /// calls to `get_next`
+    Steps(f32),
+    Seconds(f32),
+    Beats(f32),
+}
+
+/// Tracks how far a time based generator is into its cycle. Cycles in
+/// seconds count from its first step, so a freshly evaluated one starts at
+/// the beginning. Cycles in beats follow the beat grid instead, so `1b`
+/// lines up with bars and with `/n`.
+struct Timer {
+    start: Option<f64>,
+}
+
+impl Timer {
+    fn new() -> Self {
+        Timer { start: None }
+    }
+
+    /// Position within the current cycle, from 0 to 1. Doesn't apply to
+    /// `Period::Steps`, which counts calls instead.
+    fn phase(&mut self, period: Period, clock: &Clock) -> f32 {
+        self.cycles(period, clock).fract() as f32
+    }
+
+    /// Cycles so far, on the beat grid for `Period::Beats`.
+    fn cycles(&mut self, period: Period, clock: &Clock) -> f64 {
+        match period {
+            Period::Beats(length) if length > 0.0 => clock.cycles(length as f64),
+            _ => self.elapsed(period, clock),
+        }
+    }
+
+    /// Cycles since the first call, for one-shots that start when they're
+    /// evaluated.
+    fn elapsed(&mut self, period: Period, clock: &Clock) -> f64 {
+        let (now, length) = match period {
+            Period::Steps(_) => return 0.0,
+            Period::Seconds(length) => (clock.seconds(), length),
+            Period::Beats(length) => (clock.beats(), length),
+        };
+        let start = *self.start.get_or_insert(now);
+        if length <= 0.0 {
+            return 0.0;
+        }
+        (now - start) / length as f64
+    }
 }
 
 // Static
@@ -16,7 +181,7 @@ impl StaticParameter {
 }
 
 impl Parameter for StaticParameter {
-    fn get_next(&mut self) -> f32 {
+    fn get_next(&mut self, _ctx: &mut Context) -> f32 {
         self.value
     }
 }
@@ -27,25 +192,137 @@ impl Parameter for StaticParameter {
 
 pub struct ChooseParameter {
     items: Vec<f32>,
+    /// index of the previous pick, if it mustn't come up again right away
+    last: Option<usize>,
+    no_repeat: bool,
 }
 
 impl ChooseParameter {
     pub fn from_seq(seq: &[f32]) -> Self {
         ChooseParameter {
             items: seq.to_vec(),
+            last: None,
+            no_repeat: false,
+        }
+    }
+
+    /// Never picks the same item twice in a row.
+    pub fn no_repeat(seq: &[f32]) -> Self {
+        ChooseParameter {
+            no_repeat: true,
+            ..ChooseParameter::from_seq(seq)
         }
     }
 }
 
 impl Parameter for ChooseParameter {
-    fn get_next(&mut self) -> f32 {
-        match self.items.choose(&mut rand::thread_rng()) {
-            Some(thing) => *thing,
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        if self.items.is_empty() {
+            return 0.0;
+        }
+        let index = match self.last {
+            // pick among the others, skipping over the last one
+            Some(last) if self.no_repeat && self.items.len() > 1 => {
+                let index = ctx.rng.gen_range(0..self.items.len() - 1);
+                if index >= last {
+                    index + 1
+                } else {
+                    index
+                }
+            }
+            _ => ctx.rng.gen_range(0..self.items.len()),
+        };
+        self.last = Some(index);
+        self.items[index]
+    }
+}
+
+/////////////////////
+// WEIGHTED CHOOSE //
+/////////////////////
+
+pub struct WeightedChooseParameter {
+    items: Vec<f32>,
+    /// running sum of the weights, same length as `items`
+    cumulative: Vec<f32>,
+}
+
+/// Running sum of `weights`, negative weights count as 0.
+fn cumulative(weights: impl Iterator<Item = f32>) -> Vec<f32> {
+    let mut total = 0.0;
+    weights
+        .map(|weight| {
+            total += weight.max(0.0);
+            total
+        })
+        .collect()
+}
+
+/// Index into the weights that `cumulative` was built from, `None` if
+/// they're all 0.
+fn pick_weighted(cumulative: &[f32], rng: &mut StdRng) -> Option<usize> {
+    let total = *cumulative.last().filter(|total| **total > 0.0)?;
+    let r = rng.gen::<f32>() * total;
+    cumulative
+        .iter()
+        .position(|sum| r < *sum)
+        .or(Some(cumulative.len() - 1))
+}
+
+impl WeightedChooseParameter {
+    /// `pairs` are `(value, weight)`, weights don't need to add up to 1.
+    pub fn from_pairs(pairs: &[(f32, f32)]) -> Self {
+        WeightedChooseParameter {
+            items: pairs.iter().map(|(value, _)| *value).collect(),
+            cumulative: cumulative(pairs.iter().map(|(_, weight)| *weight)),
+        }
+    }
+}
+
+impl Parameter for WeightedChooseParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        match pick_weighted(&self.cumulative, &mut ctx.rng) {
+            Some(index) => self.items[index],
             None => 0.0,
         }
     }
 }
 
+/////////////
+// SHUFFLE //
+/////////////
+
+/// Every item once in random order, then again in a new order.
+pub struct ShuffleParameter {
+    items: Vec<f32>,
+    index: usize,
+}
+
+impl ShuffleParameter {
+    pub fn from_seq(seq: &[f32]) -> Self {
+        ShuffleParameter {
+            items: seq.to_vec(),
+            // shuffled on the first call
+            index: seq.len(),
+        }
+    }
+}
+
+impl Parameter for ShuffleParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        if self.items.is_empty() {
+            return 0.0;
+        }
+        if self.index >= self.items.len() {
+            self.items.shuffle(&mut ctx.rng);
+            self.index = 0;
+        }
+        let item = self.items[self.index];
+        self.index += 1;
+        item
+    }
+}
+
 ////////////
 // CYCLE  //
 ////////////
@@ -65,7 +342,7 @@ impl CycleParameter {
 }
 
 impl Parameter for CycleParameter {
-    fn get_next(&mut self) -> f32 {
+    fn get_next(&mut self, _ctx: &mut Context) -> f32 {
         let item = self.items[self.index];
 
         self.index += 1;
@@ -78,34 +355,200 @@ impl Parameter for CycleParameter {
     }
 }
 
+////////////
+// MARKOV //
+////////////
+
+/// Wanders between states, each with its own weighted successors.
+pub struct MarkovParameter {
+    values: Vec<f32>,
+    /// per state, cumulative weights of going to each of `next`
+    cumulative: Vec<Vec<f32>>,
+    next: Vec<Vec<usize>>,
+    state: Option<usize>,
+}
+
+impl MarkovParameter {
+    /// `successors[i]` lists `(state index, weight)` to go to from
+    /// `values[i]`. Starts at the first state.
+    pub fn new(values: &[f32], successors: &[Vec<(usize, f32)>]) -> Self {
+        MarkovParameter {
+            values: values.to_vec(),
+            cumulative: successors
+                .iter()
+                .map(|succ| cumulative(succ.iter().map(|(_, weight)| *weight)))
+                .collect(),
+            next: successors
+                .iter()
+                .map(|succ| succ.iter().map(|(state, _)| *state).collect())
+                .collect(),
+            state: None,
+        }
+    }
+}
+
+impl Parameter for MarkovParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        let state = match self.state {
+            // a dead end starts over
+            Some(state) => pick_weighted(&self.cumulative[state], &mut ctx.rng)
+                .map_or(0, |i| self.next[state][i]),
+            None => 0,
+        };
+        self.state = Some(state);
+        self.values[state]
+    }
+}
+
+/////////
+// SEQ //
+/////////
+
+/// Plays values for a given length each, a `None` value is a rest that
+/// holds whatever came before it.
+pub struct SeqParameter {
+    steps: Vec<(Option<f32>, Period)>,
+    index: usize,
+    /// calls since the current step started
+    count: f32,
+    timer: Timer,
+    value: f32,
+}
+
+impl SeqParameter {
+    pub fn new(steps: &[(Option<f32>, Period)]) -> Self {
+        SeqParameter {
+            steps: steps.to_vec(),
+            index: 0,
+            count: 0.0,
+            timer: Timer::new(),
+            // it loops, so a rest at the start holds the last value
+            value: steps
+                .iter()
+                .rev()
+                .find_map(|(value, _)| *value)
+                .unwrap_or(0.0),
+        }
+    }
+
+    fn step_done(&mut self, clock: &Clock) -> bool {
+        match self.steps[self.index].1 {
+            Period::Steps(length) => self.count >= length,
+            period => self.timer.elapsed(period, clock) >= 1.0,
+        }
+    }
+}
+
+impl Parameter for SeqParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        if self.steps.is_empty() {
+            return self.value;
+        }
+        // skips steps too short to show up at all, but never loops forever
+        for _ in 0..self.steps.len() {
+            if !self.step_done(&ctx.clock) {
+                break;
+            }
+            self.index = (self.index + 1) % self.steps.len();
+            self.count = 0.0;
+            self.timer = Timer::new();
+        }
+        // starts the timer of a fresh step
+        self.step_done(&ctx.clock);
+        self.count += 1.0;
+
+        if let Some(value) = self.steps[self.index].0 {
+            self.value = value;
+        }
+        self.value
+    }
+}
+
 //////////
 // RAMP //
 //////////
 
+/// What a ramp does once it reaches `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RampMode {
+    /// stays at `max`
+    Once,
+    /// starts over from `min`
+    Loop,
+    /// heads back down to `min`, and up again
+    PingPong,
+}
+
 pub struct RampParameter {
     min: f32,
+    max: f32,
     inc: f32,
     steps: f32,
     step_count: f32,
+    period: Period,
+    mode: RampMode,
+    timer: Timer,
 }
 
 impl RampParameter {
     pub fn from_params(min: f32, max: f32, steps: f32) -> Self {
+        RampParameter::with_period(min, max, Period::Steps(steps))
+    }
+
+    pub fn with_period(min: f32, max: f32, period: Period) -> Self {
+        RampParameter::with_mode(min, max, period, RampMode::Loop)
+    }
+
+    pub fn with_mode(min: f32, max: f32, period: Period, mode: RampMode) -> Self {
+        let steps = match period {
+            Period::Steps(steps) => steps,
+            _ => 0.0,
+        };
         RampParameter {
             min,
+            max,
             inc: (max - min) / steps,
             steps,
             step_count: 0.0,
+            period,
+            mode,
+            timer: Timer::new(),
         }
     }
 }
 
 impl Parameter for RampParameter {
-    fn get_next(&mut self) -> f32 {
-        let cur = self.min + self.step_count * self.inc;
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        if !matches!(self.period, Period::Steps(_)) {
+            let phase = match self.mode {
+                RampMode::Once => (self.timer.elapsed(self.period, &ctx.clock) as f32).min(1.0),
+                RampMode::Loop => self.timer.phase(self.period, &ctx.clock),
+                RampMode::PingPong => {
+                    let cycles = self.timer.cycles(self.period, &ctx.clock) as f32;
+                    1.0 - (cycles % 2.0 - 1.0).abs()
+                }
+            };
+            return self.min + phase * (self.max - self.min);
+        }
+
+        // ping pong turns around at the last whole step below `steps`, and
+        // counts on up to twice that on the way back
+        let turn = self.steps.floor();
+        let position = match self.mode {
+            RampMode::PingPong if self.step_count > turn => 2.0 * turn - self.step_count,
+            _ => self.step_count,
+        };
+        let cur = self.min + position * self.inc;
         self.step_count += 1.0;
-        if self.step_count > self.steps {
-            self.step_count = 0.0;
+        match self.mode {
+            RampMode::Once => self.step_count = self.step_count.min(self.steps),
+            RampMode::Loop if self.step_count > self.steps => self.step_count = 0.0,
+            // without playing `min` and the turning point twice
+            RampMode::PingPong if self.step_count >= 2.0 * turn => self.step_count = 0.0,
+            _ => {}
         }
         cur
     }
@@ -122,10 +565,20 @@ pub struct BounceParameter {
     range: f32,
     steps: f32,
     step_count: f32,
+    period: Period,
+    timer: Timer,
 }
 
 impl BounceParameter {
     pub fn from_params(min: f32, max: f32, steps: f32) -> Self {
+        BounceParameter::with_period(min, max, Period::Steps(steps))
+    }
+
+    pub fn with_period(min: f32, max: f32, period: Period) -> Self {
+        let steps = match period {
+            Period::Steps(steps) => steps,
+            _ => 0.0,
+        };
         let mut dec_inc: f32 = 360.0;
         dec_inc /= steps;
         BounceParameter {
@@ -134,12 +587,19 @@ impl BounceParameter {
             degree_inc: dec_inc,
             steps,
             step_count: 0.0,
+            period,
+            timer: Timer::new(),
         }
     }
 }
 
 impl Parameter for BounceParameter {
-    fn get_next(&mut self) -> f32 {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        if !matches!(self.period, Period::Steps(_)) {
+            let degree = self.timer.phase(self.period, &ctx.clock) * 360.0;
+            return self.min + degree.to_radians().sin().abs() * self.range;
+        }
+
         // why doesn't rust has a hashable float ?????
         let deg_inc_raw: f32 = self.degree_inc;
         let mut step_count_raw: f32 = self.step_count;
@@ -159,28 +619,1142 @@ impl Parameter for BounceParameter {
     }
 }
 
+/////////
+// LFO //
+/////////
+
+/// Waveforms, all going from 0 to 1 over one cycle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Triangle,
+    /// rising
+    Saw,
+    /// falling
+    InvSaw,
+    /// a full sine, not the absolute one of `bounce`
+    Sine,
+    /// high for the first `duty` fraction of the cycle
+    Square(f32),
+}
+
+impl Shape {
+    fn at(self, phase: f32) -> f32 {
+        match self {
+            Shape::Triangle => 1.0 - (2.0 * phase - 1.0).abs(),
+            Shape::Saw => phase,
+            Shape::InvSaw => 1.0 - phase,
+            Shape::Sine => ((phase * std::f32::consts::TAU).sin() + 1.0) / 2.0,
+            Shape::Square(duty) if phase < duty => 1.0,
+            Shape::Square(_) => 0.0,
+        }
+    }
+}
+
+pub struct LfoParameter {
+    shape: Shape,
+    min: f32,
+    range: f32,
+    period: Period,
+    /// where in the cycle it starts, from 0 to 1
+    phase: f32,
+    step_count: f32,
+    timer: Timer,
+}
+
+impl LfoParameter {
+    pub fn new(shape: Shape, min: f32, max: f32, period: Period, phase: f32) -> Self {
+        LfoParameter {
+            shape,
+            min,
+            range: max - min,
+            period,
+            phase,
+            step_count: 0.0,
+            timer: Timer::new(),
+        }
+    }
+}
+
+impl Parameter for LfoParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        let position = match self.period {
+            Period::Steps(steps) if steps > 0.0 => {
+                let position = self.step_count / steps;
+                self.step_count = (self.step_count + 1.0) % steps;
+                position
+            }
+            Period::Steps(_) => 0.0,
+            _ => self.timer.phase(self.period, &ctx.clock),
+        };
+        let phase = (position + self.phase).rem_euclid(1.0);
+        self.min + self.shape.at(phase) * self.range
+    }
+}
+
+///////////
+// NOISE //
+///////////
+
+/// Random value from 0 to 1 for a point of the lattice, always the same
+/// for the same seed.
+fn lattice(seed: u64, x: i64, y: i64) -> f32 {
+    // splitmix64 finalizer
+    let mut h = seed
+        ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^= h >> 31;
+    (h >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// Value noise from 0 to 1, interpolated so that it has no kinks at the
+/// lattice points.
+fn value_noise(seed: u64, x: f64, y: f64) -> f32 {
+    let fade = |t: f64| (t * t * t * (t * (t * 6.0 - 15.0) + 10.0)) as f32;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (fade(x - x0), fade(y - y0));
+    let (x0, y0) = (x0 as i64, y0 as i64);
+
+    let top = lattice(seed, x0, y0) * (1.0 - tx) + lattice(seed, x0 + 1, y0) * tx;
+    let bottom = lattice(seed, x0, y0 + 1) * (1.0 - tx) + lattice(seed, x0 + 1, y0 + 1) * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+// drifts smoothly to a new random value every `period`
+pub struct NoiseParameter {
+    min: f32,
+    range: f32,
+    period: Period,
+    /// where in the field to sample, `None` for one of its own
+    y: Option<f32>,
+    seed: Option<u64>,
+    step_count: f32,
+    timer: Timer,
+}
+
+impl NoiseParameter {
+    /// One dimensional noise, different for every parameter.
+    pub fn new(min: f32, max: f32, period: Period) -> Self {
+        NoiseParameter {
+            min,
+            range: max - min,
+            period,
+            y: None,
+            seed: None,
+            step_count: 0.0,
+            timer: Timer::new(),
+        }
+    }
+
+    /// A line through the noise field shared by all parameters, at `y`.
+    /// Nearby `y`s give similar values.
+    pub fn field(min: f32, max: f32, period: Period, y: f32) -> Self {
+        NoiseParameter {
+            y: Some(y),
+            ..NoiseParameter::new(min, max, period)
+        }
+    }
+}
+
+impl Parameter for NoiseParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        let y = self.y;
+        let seed = *self.seed.get_or_insert_with(|| match y {
+            Some(_) => ctx.noise_seed,
+            None => ctx.rng.gen(),
+        });
+
+        let x = match self.period {
+            Period::Steps(steps) if steps > 0.0 => {
+                let x = self.step_count / steps;
+                self.step_count += 1.0;
+                x as f64
+            }
+            Period::Steps(_) => 0.0,
+            _ => self.timer.cycles(self.period, &ctx.clock),
+        };
+
+        self.min + value_noise(seed, x, y.unwrap_or(0.0) as f64) * self.range
+    }
+}
+
+////////////
+// EASING //
+////////////
+
+// reshapes another parameter going from `min` to `max`
+pub struct EaseParameter {
+    inner: Box<dyn Parameter>,
+    easing: Easing,
+    min: f32,
+    range: f32,
+}
+
+impl EaseParameter {
+    pub fn new(inner: Box<dyn Parameter>, easing: Easing, min: f32, max: f32) -> Self {
+        EaseParameter {
+            inner,
+            easing,
+            min,
+            range: max - min,
+        }
+    }
+}
+
+impl Parameter for EaseParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        let val = self.inner.get_next(ctx);
+        if self.range == 0.0 {
+            return val;
+        }
+        self.min + self.easing.apply((val - self.min) / self.range) * self.range
+    }
+}
+
+/////////
+// ENV //
+/////////
+
+/// What opens the gate of an envelope.
+pub enum Trigger {
+    /// while the key is held
+    Key(String),
+    /// for the first half of every this many beats
+    Beat(f32),
+    /// while the parameter is above the threshold
+    Above(Box<dyn Parameter>, f32),
+}
+
+impl Trigger {
+    fn gate(&mut self, ctx: &mut Context) -> bool {
+        match self {
+            Trigger::Key(key) => ctx.key_down(key),
+            Trigger::Beat(beats) => ctx.clock.tick(2.0 / *beats as f64).is_multiple_of(2),
+            Trigger::Above(param, threshold) => param.get_next(ctx) > *threshold,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Attack, decay, sustain, release, going from 0 to 1 and back whenever
+/// its trigger opens and closes the gate. Retriggering picks up from
+/// wherever the level is.
+pub struct EnvParameter {
+    attack: Period,
+    decay: Period,
+    sustain: f32,
+    release: Period,
+    trigger: Trigger,
+    gate: bool,
+    stage: Stage,
+    level: f32,
+    /// level at the start of the current stage
+    from: f32,
+    /// calls since the current stage started
+    count: f32,
+    timer: Timer,
+}
+
+impl EnvParameter {
+    pub fn new(
+        attack: Period,
+        decay: Period,
+        sustain: f32,
+        release: Period,
+        trigger: Trigger,
+    ) -> Self {
+        EnvParameter {
+            attack,
+            decay,
+            sustain,
+            release,
+            trigger,
+            gate: false,
+            stage: Stage::Idle,
+            level: 0.0,
+            from: 0.0,
+            count: 0.0,
+            timer: Timer::new(),
+        }
+    }
+
+    fn enter(&mut self, stage: Stage) {
+        self.stage = stage;
+        self.from = self.level;
+        self.count = 0.0;
+        self.timer = Timer::new();
+    }
+
+    /// How far into the current stage, from 0 to 1.
+    fn progress(&mut self, period: Period, clock: &Clock) -> f32 {
+        let progress = match period {
+            Period::Steps(length) | Period::Seconds(length) | Period::Beats(length)
+                if length <= 0.0 =>
+            {
+                1.0
+            }
+            Period::Steps(length) => self.count / length,
+            period => self.timer.elapsed(period, clock) as f32,
+        };
+        self.count += 1.0;
+        progress.min(1.0)
+    }
+}
+
+impl Parameter for EnvParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        let gate = self.trigger.gate(ctx);
+        if gate && !self.gate {
+            self.enter(Stage::Attack);
+        } else if !gate && self.gate {
+            self.enter(Stage::Release);
+        }
+        self.gate = gate;
+
+        // a stage that's over hands over to the next one right away, so
+        // zero length stages take no time
+        loop {
+            let (period, target, next) = match self.stage {
+                Stage::Idle | Stage::Sustain => break,
+                Stage::Attack => (self.attack, 1.0, Stage::Decay),
+                Stage::Decay => (self.decay, self.sustain, Stage::Sustain),
+                Stage::Release => (self.release, 0.0, Stage::Idle),
+            };
+            let t = self.progress(period, &ctx.clock);
+            self.level = self.from + (target - self.from) * t;
+            if t < 1.0 {
+                break;
+            }
+            self.enter(next);
+        }
+        if self.stage == Stage::Sustain {
+            self.level = self.sustain;
+        }
+        self.level
+    }
+}
+
+///////////////
+// MODIFIERS //
+///////////////
+
+pub struct ClampParameter {
+    inner: Box<dyn Parameter>,
+    min: f32,
+    max: f32,
+}
+
+impl ClampParameter {
+    pub fn new(inner: Box<dyn Parameter>, min: f32, max: f32) -> Self {
+        // either order works
+        ClampParameter {
+            inner,
+            min: min.min(max),
+            max: min.max(max),
+        }
+    }
+}
+
+impl Parameter for ClampParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        self.inner.get_next(ctx).clamp(self.min, self.max)
+    }
+}
+
+/// Rounds to the nearest multiple of `step`.
+pub struct QuantParameter {
+    inner: Box<dyn Parameter>,
+    step: f32,
+}
+
+impl QuantParameter {
+    pub fn new(inner: Box<dyn Parameter>, step: f32) -> Self {
+        QuantParameter { inner, step }
+    }
+}
+
+impl Parameter for QuantParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        let val = self.inner.get_next(ctx);
+        if self.step == 0.0 {
+            return val;
+        }
+        (val / self.step).round() * self.step
+    }
+}
+
+/// Glides towards the value of another parameter, covering about two
+/// thirds of the distance within `length`.
+pub struct LagParameter {
+    inner: Box<dyn Parameter>,
+    length: Period,
+    value: Option<f32>,
+    /// time of the previous call, in seconds or beats
+    last: f64,
+}
+
+impl LagParameter {
+    pub fn new(inner: Box<dyn Parameter>, length: Period) -> Self {
+        LagParameter {
+            inner,
+            length,
+            value: None,
+            last: 0.0,
+        }
+    }
+}
+
+impl Parameter for LagParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        let target = self.inner.get_next(ctx);
+        let (now, length) = match self.length {
+            Period::Steps(length) => (self.last + 1.0, length),
+            Period::Seconds(length) => (ctx.clock.seconds(), length),
+            Period::Beats(length) => (ctx.clock.beats(), length),
+        };
+        let value = match self.value {
+            Some(value) if length > 0.0 => {
+                let amount = 1.0 - (-(now - self.last) / length as f64).exp();
+                value + (target - value) * amount as f32
+            }
+            _ => target,
+        };
+        self.last = now;
+        self.value = Some(value);
+        value
+    }
+}
+
+/// Sample and hold, takes on the value of another parameter once every
+/// `length` and keeps it in between. The other parameter keeps running.
+pub struct HoldParameter {
+    inner: Box<dyn Parameter>,
+    length: Period,
+    value: Option<f32>,
+    /// calls since the last sample
+    count: f32,
+    timer: Timer,
+}
+
+impl HoldParameter {
+    pub fn new(inner: Box<dyn Parameter>, length: Period) -> Self {
+        HoldParameter {
+            inner,
+            length,
+            value: None,
+            count: 0.0,
+            timer: Timer::new(),
+        }
+    }
+}
+
+impl Parameter for HoldParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        let val = self.inner.get_next(ctx);
+        let due = match self.length {
+            Period::Steps(length) => self.count >= length,
+            period => self.timer.elapsed(period, &ctx.clock) >= 1.0,
+        };
+        if self.value.is_none() || due {
+            self.value = Some(val);
+            self.count = 0.0;
+            self.timer = Timer::new();
+            self.timer.elapsed(self.length, &ctx.clock);
+        }
+        self.count += 1.0;
+        self.value.unwrap_or(val)
+    }
+}
+
+//////////
+// WALK //
+//////////
+
+/// What a random walk does when it steps past its bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    /// bounces back by however far it overshot
+    Reflect,
+    /// comes back in on the other side
+    Wrap,
+}
+
+/// Takes a step up or down at random every frame, staying between `min`
+/// and `max`.
+pub struct WalkParameter {
+    value: f32,
+    min: f32,
+    max: f32,
+    step: Box<dyn Parameter>,
+    edge: Edge,
+}
+
+impl WalkParameter {
+    pub fn new(start: f32, min: f32, max: f32, step: Box<dyn Parameter>, edge: Edge) -> Self {
+        // either order works
+        let (min, max) = (min.min(max), min.max(max));
+        WalkParameter {
+            value: start.clamp(min, max),
+            min,
+            max,
+            step,
+            edge,
+        }
+    }
+
+    fn constrain(&self, value: f32) -> f32 {
+        let range = self.max - self.min;
+        if (self.min..=self.max).contains(&value) {
+            return value;
+        }
+        if range <= 0.0 {
+            return self.min;
+        }
+        let offset = value - self.min;
+        match self.edge {
+            Edge::Wrap => self.min + offset.rem_euclid(range),
+            Edge::Reflect => {
+                // a step much larger than the range bounces several times
+                let folded = offset.rem_euclid(2.0 * range);
+                if folded > range {
+                    self.min + 2.0 * range - folded
+                } else {
+                    self.min + folded
+                }
+            }
+        }
+    }
+}
+
+impl Parameter for WalkParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        let cur = self.value;
+        let step = self.step.get_next(ctx);
+        let next = if ctx.rng.gen::<bool>() {
+            cur + step
+        } else {
+            cur - step
+        };
+        self.value = self.constrain(next);
+        cur
+    }
+}
+
+//////////
+// SYNC //
+//////////
+
+// holds the value of another parameter, asking for a new one on every
+// tick of the clock
+pub struct SyncParameter {
+    inner: Box<dyn Parameter>,
+    division: f64,
+    last: Option<(u64, f32)>,
+}
+
+impl SyncParameter {
+    /// Step `inner` `division` times per beat.
+    pub fn new(inner: Box<dyn Parameter>, division: f32) -> Self {
+        SyncParameter {
+            inner,
+            division: division as f64,
+            last: None,
+        }
+    }
+}
+
+impl Parameter for SyncParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        let tick = ctx.clock.tick(self.division);
+        match self.last {
+            Some((last_tick, value)) if last_tick == tick => value,
+            _ => {
+                let value = self.inner.get_next(ctx);
+                self.last = Some((tick, value));
+                value
+            }
+        }
+    }
+}
+
+//////////////
+// VARIABLE //
+//////////////
+
+/// One parameter read from several places, like a variable that has no
+/// name. Clones share it, and it advances once per frame.
+#[derive(Clone)]
+pub struct SharedParameter {
+    inner: Rc<RefCell<Variable>>,
+}
+
+impl SharedParameter {
+    pub fn new(param: Box<dyn Parameter>) -> Self {
+        SharedParameter {
+            inner: Rc::new(RefCell::new(Variable::new(param, String::new()))),
+        }
+    }
+}
+
+impl Parameter for SharedParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        self.inner.borrow_mut().read(ctx)
+    }
+}
+
+// reads a `let`, which lives in the context
+pub struct VarParameter {
+    name: String,
+}
+
+impl VarParameter {
+    pub fn new(name: &str) -> Self {
+        VarParameter {
+            name: name.to_string(),
+        }
+    }
+}
+
+impl Parameter for VarParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        ctx.read_variable(&self.name)
+    }
+}
+
+////////////////
+// ARITHMETIC //
+////////////////
+
+pub struct NegParameter {
+    inner: Box<dyn Parameter>,
+}
+
+impl NegParameter {
+    pub fn new(inner: Box<dyn Parameter>) -> Self {
+        NegParameter { inner }
+    }
+}
+
+impl Parameter for NegParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        -self.inner.get_next(ctx)
+    }
+}
+
+// both sides advance on every step, left first
+pub struct BinaryParameter {
+    op: BinOp,
+    left: Box<dyn Parameter>,
+    right: Box<dyn Parameter>,
+}
+
+impl BinaryParameter {
+    pub fn new(op: BinOp, left: Box<dyn Parameter>, right: Box<dyn Parameter>) -> Self {
+        BinaryParameter { op, left, right }
+    }
+}
+
+impl Parameter for BinaryParameter {
+    fn get_next(&mut self, ctx: &mut Context) -> f32 {
+        let left = self.left.get_next(ctx);
+        let right = self.right.get_next(ctx);
+        self.op.apply(left, right)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
 
+    #[test]
+    fn test_choose_gen_seeded() {
+        let mut results = Vec::new();
+        for _ in 0..2 {
+            let mut ctx = Context::new(42);
+            let mut choose_gen = ChooseParameter::from_seq(&[1.0, 2.0, 3.0, 4.0]);
+            results.push(
+                (0..20)
+                    .map(|_| choose_gen.get_next(&mut ctx))
+                    .collect::<Vec<_>>(),
+            );
+        }
+        assert_eq!(results[0], results[1]);
+    }
+
+    #[test]
+    fn test_choose_no_repeat() {
+        let mut ctx = Context::new(3);
+        let mut choose_gen = ChooseParameter::no_repeat(&[1.0, 2.0, 3.0]);
+        let values: Vec<f32> = (0..100).map(|_| choose_gen.get_next(&mut ctx)).collect();
+        assert!(values.windows(2).all(|pair| pair[0] != pair[1]));
+        for item in [1.0, 2.0, 3.0] {
+            assert!(values.contains(&item));
+        }
+
+        // nothing else to pick
+        let mut single = ChooseParameter::no_repeat(&[5.0]);
+        assert_eq!(single.get_next(&mut ctx), 5.0);
+        assert_eq!(single.get_next(&mut ctx), 5.0);
+    }
+
+    #[test]
+    fn test_wchoose_gen() {
+        let mut ctx = Context::new(7);
+        let mut wchoose_gen =
+            WeightedChooseParameter::from_pairs(&[(1.0, 3.0), (2.0, 1.0), (3.0, 0.0)]);
+        let values: Vec<f32> = (0..1000).map(|_| wchoose_gen.get_next(&mut ctx)).collect();
+        let ones = values.iter().filter(|v| **v == 1.0).count();
+        assert!((700..800).contains(&ones), "{}", ones);
+        assert!(!values.contains(&3.0));
+    }
+
+    #[test]
+    fn test_shuffle_gen() {
+        let mut ctx = Context::new(1);
+        let mut shuffle_gen = ShuffleParameter::from_seq(&[1.0, 2.0, 3.0, 4.0]);
+        for _ in 0..5 {
+            let mut round: Vec<f32> = (0..4).map(|_| shuffle_gen.get_next(&mut ctx)).collect();
+            round.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(round, [1.0, 2.0, 3.0, 4.0]);
+        }
+    }
+
+    #[test]
+    fn test_markov_gen() {
+        let mut ctx = Context::new(9);
+        // 0 always goes to 1, 1 goes back to 0 three times as often as to 2,
+        // 2 is a dead end and starts over
+        let mut markov_gen = MarkovParameter::new(
+            &[0.0, 1.0, 2.0],
+            &[vec![(1, 1.0)], vec![(0, 3.0), (2, 1.0)], vec![]],
+        );
+        let values: Vec<f32> = (0..1000).map(|_| markov_gen.get_next(&mut ctx)).collect();
+        assert_eq!(values[0], 0.0);
+        for pair in values.windows(2) {
+            match pair[0] {
+                0.0 | 2.0 => assert_eq!(pair[1], if pair[0] == 0.0 { 1.0 } else { 0.0 }),
+                _ => assert_ne!(pair[1], 1.0),
+            }
+        }
+        let twos = values.iter().filter(|v| **v == 2.0).count();
+        assert!((80..150).contains(&twos), "{}", twos);
+    }
+
+    #[test]
+    fn test_seq_gen() {
+        let mut ctx = Context::new(0);
+        let mut seq_gen = SeqParameter::new(&[
+            (Some(1.0), Period::Steps(2.0)),
+            (None, Period::Steps(1.0)),
+            (Some(3.0), Period::Steps(3.0)),
+        ]);
+        let values: Vec<f32> = (0..8).map(|_| seq_gen.get_next(&mut ctx)).collect();
+        assert_eq!(values, [1.0, 1.0, 1.0, 3.0, 3.0, 3.0, 1.0, 1.0]);
+
+        // a rest first holds the last value, durations follow the clock
+        let mut seq_gen = SeqParameter::new(&[
+            (None, Period::Beats(1.0)),
+            (Some(5.0), Period::Seconds(1.0)),
+        ]);
+        let mut values = Vec::new();
+        for _ in 0..8 {
+            values.push(seq_gen.get_next(&mut ctx));
+            // 120 bpm, a beat every 2 frames
+            ctx.clock.advance(0.25);
+        }
+        assert_eq!(values, [5.0; 8]);
+        let mut seq_gen = SeqParameter::new(&[
+            (Some(1.0), Period::Beats(1.0)),
+            (Some(2.0), Period::Seconds(1.0)),
+        ]);
+        let mut values = Vec::new();
+        for _ in 0..8 {
+            values.push(seq_gen.get_next(&mut ctx));
+            ctx.clock.advance(0.25);
+        }
+        assert_eq!(values, [1.0, 1.0, 2.0, 2.0, 2.0, 2.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_env_gen() {
+        let mut ctx = Context::new(0);
+        let mut env_gen = EnvParameter::new(
+            Period::Steps(2.0),
+            Period::Steps(2.0),
+            0.5,
+            Period::Steps(4.0),
+            Trigger::Key("a".to_string()),
+        );
+        let mut values = Vec::new();
+        for i in 0..12 {
+            match i {
+                2 => ctx.press_key("a"),
+                8 => ctx.release_key("a"),
+                _ => {}
+            }
+            values.push(env_gen.get_next(&mut ctx));
+        }
+        assert_eq!(
+            values,
+            [0.0, 0.0, 0.0, 0.5, 1.0, 0.75, 0.5, 0.5, 0.5, 0.375, 0.25, 0.125]
+        );
+
+        // open for the first beat of every 2, at 120 bpm that's 2 frames
+        let mut ctx = Context::new(0);
+        let mut env_gen = EnvParameter::new(
+            Period::Seconds(0.0),
+            Period::Seconds(0.0),
+            1.0,
+            Period::Beats(1.0),
+            Trigger::Beat(2.0),
+        );
+        let mut values = Vec::new();
+        for _ in 0..8 {
+            values.push(env_gen.get_next(&mut ctx));
+            ctx.clock.advance(0.25);
+        }
+        assert_eq!(values, [1.0, 1.0, 1.0, 0.5, 1.0, 1.0, 1.0, 0.5]);
+
+        // retriggered by a ramp crossing 0.5
+        let mut env_gen = EnvParameter::new(
+            Period::Steps(0.0),
+            Period::Steps(0.0),
+            1.0,
+            Period::Steps(0.0),
+            Trigger::Above(Box::new(RampParameter::from_params(0.0, 1.0, 4.0)), 0.5),
+        );
+        let values: Vec<f32> = (0..8).map(|_| env_gen.get_next(&mut ctx)).collect();
+        assert_eq!(values, [0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let mut ctx = Context::new(0);
+        let ramp = || Box::new(RampParameter::from_params(0.0, 10.0, 10.0));
+
+        let mut clamp = ClampParameter::new(ramp(), 8.0, 2.0);
+        let values: Vec<f32> = (0..11).map(|_| clamp.get_next(&mut ctx)).collect();
+        assert_eq!(
+            values,
+            [2.0, 2.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 8.0, 8.0]
+        );
+
+        let mut quant = QuantParameter::new(ramp(), 4.0);
+        let values: Vec<f32> = (0..7).map(|_| quant.get_next(&mut ctx)).collect();
+        assert_eq!(values, [0.0, 0.0, 4.0, 4.0, 4.0, 4.0, 8.0]);
+
+        let mut hold = HoldParameter::new(ramp(), Period::Steps(3.0));
+        let values: Vec<f32> = (0..7).map(|_| hold.get_next(&mut ctx)).collect();
+        assert_eq!(values, [0.0, 0.0, 0.0, 3.0, 3.0, 3.0, 6.0]);
+
+        // a jump from 0 to 1 gets about two thirds of the way in one length
+        let mut lag = LagParameter::new(
+            Box::new(CycleParameter::from_seq(&[0.0, 1.0, 1.0, 1.0])),
+            Period::Steps(2.0),
+        );
+        let values: Vec<f32> = (0..3).map(|_| lag.get_next(&mut ctx)).collect();
+        assert_eq!(values[0], 0.0);
+        assert!((values[2] - (1.0 - (-1f32).exp())).abs() < 1e-6);
+
+        let mut lag = LagParameter::new(
+            Box::new(CycleParameter::from_seq(&[0.0, 1.0])),
+            Period::Seconds(0.0),
+        );
+        assert_eq!(lag.get_next(&mut ctx), 0.0);
+        assert_eq!(lag.get_next(&mut ctx), 1.0);
+    }
+
+    #[test]
+    fn test_walk_gen() {
+        let mut ctx = Context::new(5);
+        let step = || Box::new(StaticParameter::from_val(3.0));
+
+        let mut walk_gen = WalkParameter::new(5.0, 0.0, 10.0, step(), Edge::Reflect);
+        let values: Vec<f32> = (0..200).map(|_| walk_gen.get_next(&mut ctx)).collect();
+        assert_eq!(values[0], 5.0);
+        assert!(values.iter().all(|v| (0.0..=10.0).contains(v)));
+        assert!(values
+            .windows(2)
+            .any(|pair| (pair[0] - pair[1]).abs() != 3.0));
+
+        let mut walk_gen = WalkParameter::new(5.0, 0.0, 10.0, step(), Edge::Wrap);
+        let values: Vec<f32> = (0..200).map(|_| walk_gen.get_next(&mut ctx)).collect();
+        assert!(values.iter().all(|v| (0.0..=10.0).contains(v)));
+
+        let walk_gen = WalkParameter::new(0.0, 0.0, 10.0, step(), Edge::Reflect);
+        assert_eq!(walk_gen.constrain(12.0), 8.0);
+        assert_eq!(walk_gen.constrain(-3.0), 3.0);
+        assert_eq!(walk_gen.constrain(27.0), 7.0);
+        let walk_gen = WalkParameter::new(0.0, 0.0, 10.0, step(), Edge::Wrap);
+        assert_eq!(walk_gen.constrain(12.0), 2.0);
+        assert_eq!(walk_gen.constrain(-3.0), 7.0);
+
+        // two walks sharing a step that advances once per frame
+        let shared = SharedParameter::new(Box::new(CycleParameter::from_seq(&[1.0, 2.0])));
+        let mut a = WalkParameter::new(0.0, -10.0, 10.0, Box::new(shared.clone()), Edge::Reflect);
+        let mut b = WalkParameter::new(0.0, -10.0, 10.0, Box::new(shared), Edge::Reflect);
+        let mut steps = Vec::new();
+        for _ in 0..4 {
+            let (a0, b0) = (a.value, b.value);
+            a.get_next(&mut ctx);
+            b.get_next(&mut ctx);
+            steps.push(((a.value - a0).abs(), (b.value - b0).abs()));
+            ctx.clock.advance(1.0);
+        }
+        assert_eq!(steps, [(1.0, 1.0), (2.0, 2.0), (1.0, 1.0), (2.0, 2.0)]);
+    }
+
+    #[test]
+    fn test_beat_grid() {
+        // first evaluated halfway into the second beat
+        let mut ctx = Context::new(0);
+        for _ in 0..3 {
+            ctx.clock.advance(0.25);
+        }
+        let mut saw = LfoParameter::new(Shape::Saw, 0.0, 1.0, Period::Beats(1.0), 0.0);
+        let mut ramp = RampParameter::with_period(0.0, 4.0, Period::Beats(2.0));
+        let mut once = RampParameter::with_mode(0.0, 4.0, Period::Beats(2.0), RampMode::Once);
+        let mut cycle = SyncParameter::new(Box::new(CycleParameter::from_seq(&[0.0, 1.0])), 1.0);
+
+        // the beat grid is a hair early, so whole beats count as whole
+        let round = |v: f32| (v * 1e6).round() / 1e6;
+        let mut values = Vec::new();
+        for _ in 0..4 {
+            values.push((
+                round(saw.get_next(&mut ctx)),
+                round(ramp.get_next(&mut ctx)),
+                round(once.get_next(&mut ctx)),
+                cycle.get_next(&mut ctx),
+            ));
+            ctx.clock.advance(0.25);
+        }
+        // the saw restarts on the beat, when `/1` moves on, the ramp
+        // follows the bars of 2 beats, a one-shot starts where it's evaluated
+        assert_eq!(
+            values,
+            [
+                (0.5, 3.0, 0.0, 0.0),
+                (0.0, 0.0, 1.0, 1.0),
+                (0.5, 1.0, 2.0, 1.0),
+                (0.0, 2.0, 3.0, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shared_variable() {
+        let mut ctx = Context::new(0);
+        ctx.define(
+            "wob".to_string(),
+            Variable::new(
+                Box::new(RampParameter::from_params(0.0, 10.0, 10.0)),
+                "[ramp 0 10 10]".to_string(),
+            ),
+        );
+        let mut a = VarParameter::new("wob");
+        let mut b = VarParameter::new("wob");
+
+        let mut results = Vec::new();
+        for _ in 0..3 {
+            ctx.clock.advance(1.0);
+            results.push((a.get_next(&mut ctx), b.get_next(&mut ctx)));
+        }
+        assert_eq!(results, [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]);
+
+        // redefining it the same way keeps it going
+        ctx.define(
+            "wob".to_string(),
+            Variable::new(
+                Box::new(RampParameter::from_params(0.0, 10.0, 10.0)),
+                "[ramp 0 10 10]".to_string(),
+            ),
+        );
+        ctx.clock.advance(1.0);
+        assert_eq!(b.get_next(&mut ctx), 3.0);
+        assert_eq!(VarParameter::new("nope").get_next(&mut ctx), 0.0);
+    }
+
+    #[test]
+    fn test_sync_gen() {
+        let mut ctx = Context::new(0);
+        ctx.clock.set_bpm(60.0);
+        let mut sync_gen =
+            SyncParameter::new(Box::new(CycleParameter::from_seq(&[1.0, 2.0, 3.0])), 2.0);
+        let mut results = Vec::new();
+        for _ in 0..8 {
+            results.push(sync_gen.get_next(&mut ctx));
+            ctx.clock.advance(0.25);
+        }
+        assert_eq!(results, [1.0, 1.0, 2.0, 2.0, 3.0, 3.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_lfo_gen() {
+        let mut ctx = Context::new(0);
+        let mut run = |shape, min, max, phase| {
+            let mut lfo_gen = LfoParameter::new(shape, min, max, Period::Steps(4.0), phase);
+            (0..5)
+                .map(|_| lfo_gen.get_next(&mut ctx))
+                .map(|val| (val * 1000.0).round() / 1000.0)
+                .collect::<Vec<f32>>()
+        };
+        assert_eq!(
+            run(Shape::Triangle, 0.0, 1.0, 0.0),
+            [0.0, 0.5, 1.0, 0.5, 0.0]
+        );
+        assert_eq!(run(Shape::Saw, 0.0, 1.0, 0.0), [0.0, 0.25, 0.5, 0.75, 0.0]);
+        assert_eq!(
+            run(Shape::InvSaw, 0.0, 1.0, 0.0),
+            [1.0, 0.75, 0.5, 0.25, 1.0]
+        );
+        assert_eq!(run(Shape::Sine, -1.0, 1.0, 0.0), [0.0, 1.0, 0.0, -1.0, 0.0]);
+        assert_eq!(run(Shape::Sine, -1.0, 1.0, 0.5), [0.0, -1.0, 0.0, 1.0, 0.0]);
+        assert_eq!(
+            run(Shape::Square(0.25), 0.0, 1.0, 0.0),
+            [1.0, 0.0, 0.0, 0.0, 1.0]
+        );
+        assert_eq!(
+            run(Shape::Square(0.5), 0.0, 1.0, 0.75),
+            [0.0, 1.0, 1.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_noise_gen() {
+        let run = |seed: u64, mut noise_gen: NoiseParameter| {
+            let mut ctx = Context::new(seed);
+            (0..200)
+                .map(|_| noise_gen.get_next(&mut ctx))
+                .collect::<Vec<f32>>()
+        };
+
+        let a = run(7, NoiseParameter::new(-10.0, 10.0, Period::Steps(20.0)));
+        assert_eq!(
+            a,
+            run(7, NoiseParameter::new(-10.0, 10.0, Period::Steps(20.0)))
+        );
+        assert_ne!(
+            a,
+            run(8, NoiseParameter::new(-10.0, 10.0, Period::Steps(20.0)))
+        );
+        assert!(a.iter().all(|val| (-10.0..=10.0).contains(val)));
+        // no jumps
+        assert!(a.windows(2).all(|pair| (pair[1] - pair[0]).abs() < 2.0));
+
+        // close rows of the field look alike
+        let row = |y| run(7, NoiseParameter::field(0.0, 1.0, Period::Steps(20.0), y));
+        let diff = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| (a - b).abs()).sum::<f32>();
+        assert!(diff(&row(0.0), &row(0.05)) < diff(&row(0.0), &row(3.5)));
+    }
+
     #[test]
     fn test_bounce_gen() {
+        let mut ctx = Context::new(0);
         let mut bounce_gen = BounceParameter::from_params(20.0, 200.0, 10.0);
         let mut results = Vec::new();
         for _ in 0..10 {
-            results.push(bounce_gen.get_next());
+            results.push(bounce_gen.get_next(&mut ctx));
         }
         println!("Result: {:?}", results);
     }
 
+    #[test]
+    fn test_timed_gen() {
+        let mut ctx = Context::new(0);
+        let mut ramp_gen = RampParameter::with_period(0.0, 1.0, Period::Seconds(2.0));
+        let mut bounce_gen = BounceParameter::with_period(0.0, 1.0, Period::Beats(2.0));
+        let mut results = Vec::new();
+        for _ in 0..4 {
+            // reading twice doesn't make it go faster
+            ramp_gen.get_next(&mut ctx);
+            results.push((ramp_gen.get_next(&mut ctx), bounce_gen.get_next(&mut ctx)));
+            ctx.clock.advance(0.25);
+        }
+        // 120 bpm, so half a beat per step, a quarter of the bounce
+        let expected = [(0.0, 0.0), (0.125, 1.0), (0.25, 0.0), (0.375, 1.0)];
+        for ((ramp, bounce), (ramp_exp, bounce_exp)) in results.iter().zip(expected) {
+            assert!((ramp - ramp_exp).abs() < 1e-5);
+            assert!((bounce - bounce_exp).abs() < 1e-5);
+        }
+    }
+
     #[test]
     fn test_ramp_gen() {
+        let mut ctx = Context::new(0);
         let mut ramp_gen = RampParameter::from_params(20.0, 200.0, 10.0);
         let mut results = Vec::new();
         for _ in 0..10 {
-            results.push(ramp_gen.get_next());
+            results.push(ramp_gen.get_next(&mut ctx));
         }
         println!("Result: {:?}", results);
     }
+
+    #[test]
+    fn test_ramp_modes() {
+        let values = |mode, period| {
+            let mut ramp_gen = RampParameter::with_mode(0.0, 4.0, period, mode);
+            let mut ctx = Context::new(0);
+            (0..10)
+                .map(|_| {
+                    let value = ramp_gen.get_next(&mut ctx);
+                    ctx.clock.advance(0.25);
+                    value
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            values(RampMode::Loop, Period::Steps(4.0)),
+            [0.0, 1.0, 2.0, 3.0, 4.0, 0.0, 1.0, 2.0, 3.0, 4.0]
+        );
+        assert_eq!(
+            values(RampMode::Once, Period::Steps(4.0)),
+            [0.0, 1.0, 2.0, 3.0, 4.0, 4.0, 4.0, 4.0, 4.0, 4.0]
+        );
+        assert_eq!(
+            values(RampMode::PingPong, Period::Steps(4.0)),
+            [0.0, 1.0, 2.0, 3.0, 4.0, 3.0, 2.0, 1.0, 0.0, 1.0]
+        );
+
+        // with a fraction of a step left, loop and ping pong turn at the last
+        // whole step, once still ends on `max`
+        let steps = |mode, min, max, steps| {
+            let mut ramp_gen = RampParameter::with_mode(min, max, Period::Steps(steps), mode);
+            let mut ctx = Context::new(0);
+            (0..8)
+                .map(|_| ramp_gen.get_next(&mut ctx))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            steps(RampMode::Loop, 0.0, 5.0, 2.5),
+            [0.0, 2.0, 4.0, 0.0, 2.0, 4.0, 0.0, 2.0]
+        );
+        assert_eq!(
+            steps(RampMode::Once, 0.0, 5.0, 2.5),
+            [0.0, 2.0, 4.0, 5.0, 5.0, 5.0, 5.0, 5.0]
+        );
+        assert_eq!(
+            steps(RampMode::PingPong, 0.0, 7.0, 3.5),
+            [0.0, 2.0, 4.0, 6.0, 4.0, 2.0, 0.0, 2.0]
+        );
+        assert_eq!(steps(RampMode::PingPong, 0.0, 1.0, 0.5), [0.0; 8]);
+
+        assert_eq!(
+            values(RampMode::Once, Period::Seconds(1.0)),
+            [0.0, 1.0, 2.0, 3.0, 4.0, 4.0, 4.0, 4.0, 4.0, 4.0]
+        );
+        assert_eq!(
+            values(RampMode::PingPong, Period::Seconds(1.0)),
+            [0.0, 1.0, 2.0, 3.0, 4.0, 3.0, 2.0, 1.0, 0.0, 1.0]
+        );
+    }
 }
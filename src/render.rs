@@ -0,0 +1,199 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::scene::{Scene, DEFAULT_FPS, DEFAULT_HEIGHT, DEFAULT_WIDTH};
+
+pub const USAGE: &str = "usage: imgsampler render <script> [--frames <n>] [--size <w>x<h>] \
+[--fps <n>] [--out <dir>] [--assets <dir>] [--seed <n>]";
+
+/// Settings for rendering a script to a PNG sequence without a window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    pub script: PathBuf,
+    pub frames: usize,
+    pub width: u32,
+    pub height: u32,
+    /// how much time passes per frame, for durations in seconds or beats
+    pub fps: f64,
+    pub out: PathBuf,
+    /// images are loaded from `<assets>/images`
+    pub assets: PathBuf,
+    /// overrides any `seed` in the script
+    pub seed: Option<u64>,
+}
+
+impl RenderOptions {
+    /// Parse the arguments following `render`.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut script = None;
+        let mut options = RenderOptions {
+            script: PathBuf::new(),
+            frames: 100,
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            fps: DEFAULT_FPS,
+            out: PathBuf::from("frames"),
+            assets: PathBuf::from("assets"),
+            seed: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}\n{}", arg, USAGE))
+            };
+
+            match arg.as_str() {
+                "--frames" => {
+                    let frames = value()?;
+                    options.frames = frames
+                        .parse()
+                        .map_err(|_| format!("invalid frame count: {}", frames))?;
+                }
+                "--size" => {
+                    let size = value()?;
+                    let (w, h) = size
+                        .split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                        .filter(|(w, h)| *w > 0 && *h > 0)
+                        .ok_or_else(|| format!("invalid size: {}, expected <w>x<h>", size))?;
+                    options.width = w;
+                    options.height = h;
+                }
+                "--fps" => {
+                    let fps = value()?;
+                    options.fps = fps
+                        .parse()
+                        .ok()
+                        .filter(|fps: &f64| *fps > 0.0)
+                        .ok_or_else(|| format!("invalid frame rate: {}", fps))?;
+                }
+                "--out" => options.out = PathBuf::from(value()?),
+                "--assets" => options.assets = PathBuf::from(value()?),
+                "--seed" => {
+                    let seed = value()?;
+                    options.seed = Some(
+                        seed.parse()
+                            .map_err(|_| format!("invalid seed: {}", seed))?,
+                    );
+                }
+                _ if arg.starts_with("--") => {
+                    return Err(format!("unknown option: {}\n{}", arg, USAGE));
+                }
+                _ if script.is_none() => script = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument: {}\n{}", arg, USAGE)),
+            }
+        }
+
+        options.script = script.ok_or_else(|| USAGE.to_string())?;
+        Ok(options)
+    }
+}
+
+/// Evaluate the script once, then step and composite it `frames` times,
+/// writing `frame_00000.png`, `frame_00001.png` ... to the output directory.
+pub fn render(options: &RenderOptions) -> Result<(), String> {
+    let source = fs::read_to_string(&options.script)
+        .map_err(|e| format!("can't read {}: {}", options.script.display(), e))?;
+
+    let mut scene = Scene::new(&options.assets);
+    scene.set_canvas_size(options.width, options.height);
+    scene.set_fps(options.fps);
+
+    let diagnostics = scene.evaluate(&source);
+    if !diagnostics.is_empty() {
+        let mut msg = format!("{} has errors:", options.script.display());
+        for diag in diagnostics.iter() {
+            msg.push_str(&format!("\n{}", diag));
+        }
+        return Err(msg);
+    }
+
+    if let Some(seed) = options.seed {
+        scene.set_seed(seed);
+    }
+
+    fs::create_dir_all(&options.out)
+        .map_err(|e| format!("can't create {}: {}", options.out.display(), e))?;
+
+    for frame in 0..options.frames {
+        let path = options.out.join(format!("frame_{:05}.png", frame));
+        scene
+            .render_frame()
+            .save(&path)
+            .map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn args(line: &str) -> Vec<String> {
+        line.split(' ').map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_render_options() {
+        let options =
+            RenderOptions::from_args(&args("script.txt --frames 600 --size 1920x1080 --out out/"))
+                .unwrap();
+        assert_eq!(options.script, PathBuf::from("script.txt"));
+        assert_eq!(options.frames, 600);
+        assert_eq!((options.width, options.height), (1920, 1080));
+        assert_eq!(options.out, PathBuf::from("out/"));
+        assert_eq!(options.seed, None);
+        assert_eq!(options.fps, DEFAULT_FPS);
+        let options = RenderOptions::from_args(&args("script.txt --fps 60")).unwrap();
+        assert_eq!(options.fps, 60.0);
+
+        assert!(RenderOptions::from_args(&args("script.txt --size 1920")).is_err());
+        assert!(RenderOptions::from_args(&args("script.txt --frames")).is_err());
+        assert!(RenderOptions::from_args(&args("script.txt --fps 0")).is_err());
+        assert!(RenderOptions::from_args(&args("--frames 10")).is_err());
+    }
+
+    #[test]
+    fn test_render_sequence() {
+        let dir = std::env::temp_dir().join("imgsampler-test-render-sequence");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("assets").join("images")).unwrap();
+        RgbaImage::from_pixel(8, 8, Rgba([0, 0, 255, 255]))
+            .save(dir.join("assets").join("images").join("blue.png"))
+            .unwrap();
+        fs::write(
+            dir.join("script.txt"),
+            "img blue.png pos [ramp -20 20 4] [choose -10 0 10] size 10 10 scatter 2",
+        )
+        .unwrap();
+
+        let mut options =
+            RenderOptions::from_args(&args("script.txt --frames 4 --size 64x48 --seed 3")).unwrap();
+        options.script = dir.join("script.txt");
+        options.assets = dir.join("assets");
+
+        options.out = dir.join("a");
+        render(&options).unwrap();
+        options.out = dir.join("b");
+        render(&options).unwrap();
+
+        for frame in 0..4 {
+            let name = format!("frame_{:05}.png", frame);
+            let a = fs::read(dir.join("a").join(&name)).unwrap();
+            let b = fs::read(dir.join("b").join(&name)).unwrap();
+            assert_eq!(a, b);
+        }
+        assert_eq!(
+            image::open(dir.join("a").join("frame_00000.png"))
+                .unwrap()
+                .into_rgba8()
+                .dimensions(),
+            (64, 48)
+        );
+        assert!(!dir.join("a").join("frame_00004.png").exists());
+    }
+}
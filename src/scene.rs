@@ -0,0 +1,430 @@
+use image::RgbaImage;
+
+use std::path::PathBuf;
+
+use crate::clock::Clock;
+use crate::compositor::Canvas;
+use crate::diagnostic::Diagnostic;
+use crate::image_cache::ImageCache;
+use crate::interpreter;
+use crate::interpreter::Evaluation;
+use crate::layer::{self, Layer, Stamp};
+use crate::line_parser;
+use crate::parameter::Context;
+
+/// Same as the default nannou window.
+pub const DEFAULT_WIDTH: u32 = 1024;
+pub const DEFAULT_HEIGHT: u32 = 768;
+/// Frames per second the scene is stepped at.
+pub const DEFAULT_FPS: f64 = 24.0;
+/// When `advance` falls behind by more frames than this, it skips ahead
+/// rather than stepping through all of them at once.
+const MAX_CATCH_UP: usize = 4;
+
+/// The evaluated code along with the state of all its parameters.
+/// Doesn't need a window or a GPU.
+pub struct Scene {
+    asset_path: PathBuf,
+    layers: Vec<Layer>,
+    diagnostics: Vec<Diagnostic>,
+    canvas: Canvas,
+    ctx: Context,
+    fps: f64,
+    /// time passed to `advance` that isn't a whole frame yet
+    pending: f64,
+    cache: ImageCache,
+}
+
+impl Scene {
+    /// An empty scene loading images from `<asset_path>/images`.
+    /// Randomness is seeded randomly until `set_seed` is called or the
+    /// code contains a `seed` statement.
+    pub fn new(asset_path: impl Into<PathBuf>) -> Self {
+        Scene {
+            asset_path: asset_path.into(),
+            layers: Vec::new(),
+            diagnostics: Vec::new(),
+            canvas: Canvas::new(DEFAULT_WIDTH, DEFAULT_HEIGHT),
+            ctx: Context::new(rand::random()),
+            cache: ImageCache::default(),
+            fps: DEFAULT_FPS,
+            pending: 0.0,
+        }
+    }
+
+    /// Evaluate `source` with images loaded from `assets/images`, relative
+    /// to the working directory.
+    pub fn from_source(source: &str) -> Self {
+        let mut scene = Scene::new("assets");
+        scene.evaluate(source);
+        scene
+    }
+
+    /// Replace the scene with whatever `source` describes. The canvas
+    /// starts over, and so does the random sequence if there's a `seed`.
+    /// If there's anything wrong with the code, the scene keeps running as
+    /// it is and the returned diagnostics say why.
+    pub fn evaluate(&mut self, source: &str) -> &[Diagnostic] {
+        let evaluation = interpreter::evaluate(source, &self.asset_path, &mut self.cache);
+        self.apply(evaluation, true)
+    }
+
+    /// Evaluate only the block of lines around the char index `cursor`.
+    /// Layers from the block replace the ones with the same name, or are
+    /// added on top, everything else keeps running.
+    pub fn evaluate_block(&mut self, source: &str, cursor: usize) -> &[Diagnostic] {
+        let lines = line_parser::block_at(source, cursor);
+        let defined = self.ctx.variable_names().map(String::from).collect();
+        let evaluation =
+            interpreter::evaluate_lines(source, lines, &self.asset_path, &mut self.cache, &defined);
+        self.apply(evaluation, false)
+    }
+
+    fn apply(&mut self, evaluation: Evaluation, replace: bool) -> &[Diagnostic] {
+        self.diagnostics = evaluation.diagnostics;
+        if !self.diagnostics.is_empty() {
+            return &self.diagnostics;
+        }
+
+        if let Some(seed) = evaluation.seed {
+            self.ctx.reseed(seed);
+        }
+        if let Some(bpm) = evaluation.bpm {
+            self.ctx.clock.set_bpm(bpm);
+        }
+
+        if replace {
+            let names: Vec<&str> = evaluation
+                .variables
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect();
+            self.ctx.retain_variables(|name| names.contains(&name));
+        }
+        for (name, var) in evaluation.variables {
+            self.ctx.define(name, var);
+        }
+
+        let mut layers = evaluation.layers;
+        for layer in layers.iter_mut() {
+            if let Some(prev) = self
+                .layers
+                .iter_mut()
+                .find(|l| l.name == layer.name && l.instance == layer.instance)
+            {
+                layer.take_state_from(prev);
+            }
+        }
+
+        if replace {
+            self.layers = layers;
+            self.canvas.clear();
+        } else {
+            layer::merge_layers(&mut self.layers, layers);
+        }
+
+        &self.diagnostics
+    }
+
+    /// Problems found during the last evaluation.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// Decode images from disk again on the next evaluation, even if they
+    /// didn't change. Same as a `reload` statement.
+    pub fn reload_images(&mut self) {
+        self.cache.clear();
+    }
+
+    /// How many bytes of decoded images to keep around between evaluations.
+    pub fn set_cache_budget(&mut self, bytes: usize) {
+        self.cache.set_budget(bytes);
+    }
+
+    /// Restart the random sequence from `seed`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.ctx.reseed(seed);
+    }
+
+    pub fn set_canvas_size(&mut self, width: u32, height: u32) {
+        self.canvas.resize(width, height);
+    }
+
+    pub fn canvas(&self) -> &Canvas {
+        &self.canvas
+    }
+
+    pub fn set_fps(&mut self, fps: f64) {
+        self.fps = fps;
+    }
+
+    /// Seconds since the first frame, as far as parameters are concerned.
+    pub fn time(&self) -> f64 {
+        self.ctx.clock.seconds()
+    }
+
+    /// `key` is a lowercase name like `a` or `space`, for `env` triggers.
+    pub fn press_key(&mut self, key: &str) {
+        self.ctx.press_key(key);
+    }
+
+    pub fn release_key(&mut self, key: &str) {
+        self.ctx.release_key(key);
+    }
+
+    /// Tempo and beat position.
+    pub fn clock(&self) -> &Clock {
+        &self.ctx.clock
+    }
+
+    /// Let `dt` seconds of real time pass, stepping as many frames as fit.
+    /// Returns the stamps of all of them, oldest first.
+    pub fn advance(&mut self, dt: f64) -> Vec<Stamp> {
+        let frame_length = 1.0 / self.fps;
+        self.pending += dt;
+
+        let mut stamps = Vec::new();
+        let mut frames = 0;
+        while self.pending >= frame_length {
+            self.pending -= frame_length;
+            if frames == MAX_CATCH_UP {
+                self.pending %= frame_length;
+                break;
+            }
+            stamps.extend(self.step());
+            frames += 1;
+        }
+        stamps
+    }
+
+    /// Advance every layer by one frame.
+    pub fn step(&mut self) -> Vec<Stamp> {
+        self.ctx.clock.advance(1.0 / self.fps);
+        self.layers
+            .iter_mut()
+            .map(|layer| layer.step(&mut self.ctx))
+            .collect()
+    }
+
+    /// Advance every layer by one frame and draw the result on top of
+    /// the previous frames.
+    pub fn render_frame(&mut self) -> RgbaImage {
+        for stamp in self.step() {
+            self.canvas.draw(&stamp);
+        }
+        self.canvas.image().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// A fresh asset folder in the temp dir with solid colored images in
+    /// `images`, named `imgsampler-test-<name>`.
+    fn assets(name: &str, images: &[(&str, [u8; 4])]) -> PathBuf {
+        let asset_path = std::env::temp_dir().join(format!("imgsampler-test-{}", name));
+        std::fs::create_dir_all(asset_path.join("images")).unwrap();
+        for (file, color) in images.iter() {
+            RgbaImage::from_pixel(2, 2, Rgba(*color))
+                .save(asset_path.join("images").join(file))
+                .unwrap();
+        }
+        asset_path
+    }
+
+    #[test]
+    fn test_render_headless() {
+        let asset_path = assets("render-headless", &[("red.png", [255, 0, 0, 255])]);
+
+        let mut scene = Scene::new(&asset_path);
+        scene.set_canvas_size(100, 100);
+        assert!(scene
+            .evaluate("img red.png pos 20 20 size 10 10")
+            .is_empty());
+
+        let frame = scene.render_frame();
+        assert_eq!(frame.dimensions(), (100, 100));
+        // origin at the center, y up
+        assert_eq!(*frame.get_pixel(70, 30), Rgba([255, 0, 0, 255]));
+        assert_eq!(*frame.get_pixel(50, 50), Rgba([0, 0, 0, 255]));
+
+        // the same file twice, under different names
+        assert!(scene
+            .evaluate(
+                "img red.png as a pos 20 20 size 10 10\nimg red.png as b pos -20 -20 size 10 10"
+            )
+            .is_empty());
+        assert_eq!(scene.layers().len(), 2);
+        let frame = scene.render_frame();
+        assert_eq!(*frame.get_pixel(70, 30), Rgba([255, 0, 0, 255]));
+        assert_eq!(*frame.get_pixel(30, 70), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_evaluate_block() {
+        let asset_path = assets(
+            "evaluate-block",
+            &[
+                ("a.png", [255, 255, 255, 255]),
+                ("b.png", [255, 255, 255, 255]),
+            ],
+        );
+
+        let mut scene = Scene::new(&asset_path);
+        let source = "img a.png pos 0 0\n\nimg b.png pos 10 10";
+        assert!(scene.evaluate_block(source, 0).is_empty());
+        assert_eq!(scene.layers().len(), 1);
+        assert!(scene.evaluate_block(source, source.len()).is_empty());
+        assert_eq!(scene.layers().len(), 2);
+
+        // broken code leaves the running scene alone
+        let broken = "img a.png pos 0 0\n\nimg b.png pos [bouce 0 1] 10";
+        let diagnostics = scene.evaluate_block(broken, broken.len());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 3);
+        assert_eq!(scene.layers().len(), 2);
+        assert!(!scene.evaluate(broken).is_empty());
+        assert_eq!(scene.layers().len(), 2);
+
+        assert!(scene.evaluate("img b.png").is_empty());
+        assert_eq!(scene.layers().len(), 1);
+        assert!(scene.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_keep_parameter_state() {
+        let asset_path = assets(
+            "keep-parameter-state",
+            &[
+                ("a.png", [255, 255, 255, 255]),
+                ("b.png", [255, 255, 255, 255]),
+            ],
+        );
+
+        let mut scene = Scene::new(&asset_path);
+        scene.evaluate("img a.png pos [ramp 0 100 100] [ramp 0 100 100]\nimg b.png");
+        for _ in 0..10 {
+            scene.step();
+        }
+
+        // only the edited parameter restarts
+        scene.evaluate("img a.png pos [ramp 0 100 100] [ramp 0 50 100] size 10 10\nimg b.png");
+        let stamp = &scene.step()[0];
+        assert_eq!(stamp.x, 10.0);
+        assert_eq!(stamp.y, 0.0);
+
+        // formatting doesn't count as an edit
+        scene.evaluate("img a.png   pos [ramp 0  100 100]  [ramp 0 50 100]");
+        assert_eq!(scene.step()[0].x, 11.0);
+    }
+
+    #[test]
+    fn test_repeat() {
+        let asset_path = assets("repeat", &[("a.png", [255, 255, 255, 255])]);
+
+        let mut scene = Scene::new(&asset_path);
+        let source = "img a.png repeat 3 pos index [ramp 0 n 3]\n\nimg a.png as b";
+        assert!(scene.evaluate(source).is_empty());
+        assert_eq!(scene.layers().len(), 4);
+        scene.step();
+        let stamps = scene.step();
+        let positions: Vec<(f32, f32)> = stamps.iter().map(|s| (s.x, s.y)).collect();
+        assert_eq!(positions, [(0.0, 1.0), (1.0, 1.0), (2.0, 1.0), (0.0, 0.0)]);
+
+        // fewer instances replace all of the old ones
+        let source = "img a.png repeat 2 pos index [ramp 0 n 3]\n\nimg a.png as b";
+        assert!(scene.evaluate_block(source, 0).is_empty());
+        let names: Vec<(&str, usize)> = scene
+            .layers()
+            .iter()
+            .map(|l| (l.name.as_str(), l.instance))
+            .collect();
+        assert_eq!(names, [("a.png", 0), ("a.png", 1), ("b", 0)]);
+        // the ramp uses `n`, so it starts over with its new range
+        let positions: Vec<(f32, f32)> = scene.step().iter().map(|s| (s.x, s.y)).collect();
+        assert_eq!(positions, [(0.0, 0.0), (1.0, 0.0), (0.0, 0.0)]);
+        scene.step();
+        let positions: Vec<(f32, f32)> = scene.step().iter().map(|s| (s.x, s.y)).collect();
+        assert_eq!(positions, [(0.0, 4.0 / 3.0), (1.0, 4.0 / 3.0), (0.0, 0.0)]);
+
+        assert!(scene.evaluate("img a.png repeat 3 pos n 0").is_empty());
+        scene.step();
+        assert!(scene.evaluate("img a.png repeat 2 pos n 0").is_empty());
+        let xs: Vec<f32> = scene.step().iter().map(|s| s.x).collect();
+        assert_eq!(xs, [2.0, 2.0]);
+
+        let diagnostics = scene.evaluate("img a.png repeat 0");
+        assert_eq!(diagnostics[0].column, 11);
+    }
+
+    #[test]
+    fn test_shared_variables() {
+        let asset_path = assets("shared-variables", &[("a.png", [255, 255, 255, 255])]);
+
+        let mut scene = Scene::new(&asset_path);
+        let source =
+            "let wob = [ramp 0 10 10]\n\nimg a.png pos $wob $wob * 2\nimg a.png as b pos $wob 0";
+        assert!(scene.evaluate(source).is_empty());
+        scene.step();
+        let positions: Vec<(f32, f32)> = scene.step().iter().map(|s| (s.x, s.y)).collect();
+        assert_eq!(positions, [(1.0, 2.0), (1.0, 0.0)]);
+
+        // a block can use variables from elsewhere, an unchanged `let` keeps going
+        assert!(scene.evaluate_block(source, source.len()).is_empty());
+        assert_eq!(scene.step()[1].x, 2.0);
+        assert!(scene.evaluate(source).is_empty());
+        assert_eq!(scene.step()[1].x, 3.0);
+
+        let diagnostics = scene.evaluate("img a.png pos $wob $wub");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].column, 15);
+        assert_eq!(
+            diagnostics[0].expected.as_deref(),
+            Some("a `let` before it")
+        );
+    }
+
+    #[test]
+    fn test_advance() {
+        let asset_path = assets("advance", &[("a.png", [255, 255, 255, 255])]);
+
+        let mut scene = Scene::new(&asset_path);
+        scene.set_fps(10.0);
+        assert!(scene.evaluate("img a.png pos [ramp 0 10 1s] 0").is_empty());
+
+        // no frame is due yet, then two at once
+        assert!(scene.advance(0.05).is_empty());
+        let stamps = scene.advance(0.16);
+        assert_eq!(stamps.len(), 2);
+        assert_eq!(stamps[1].x, 1.0);
+
+        // a long stall doesn't step through every missed frame
+        assert_eq!(scene.advance(10.0).len(), MAX_CATCH_UP);
+        assert!((scene.time() - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_seeded_scene() {
+        let asset_path = assets("seeded-scene", &[("green.png", [0, 255, 0, 255])]);
+
+        let source = "seed 7\nimg green.png pos [choose -30 0 30] 0 size 4 4 brownian 5";
+        let mut frames = Vec::new();
+        for _ in 0..2 {
+            let mut scene = Scene::new(&asset_path);
+            scene.set_canvas_size(100, 100);
+            assert!(scene.evaluate(source).is_empty());
+            for _ in 0..5 {
+                scene.render_frame();
+            }
+            frames.push(scene.render_frame());
+        }
+        assert_eq!(frames[0], frames[1]);
+    }
+}